@@ -0,0 +1,354 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_verbosity_flag::{LogLevel, Verbosity};
+use env_logger::fmt::Color;
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use indicatif_log_bridge::LogWrapper;
+use log::Level;
+use node_cleaner::config::config::{Config, ConfigSource, Tier};
+use node_cleaner::config::excludes::{ExcludeSet, ExcludeSource};
+use node_cleaner::file_utils::remover::RemovalMode;
+use node_cleaner::file_utils::trash::default_trash_dir;
+use node_cleaner::observer::{JsonObserver, ScanObserver, SilentObserver};
+use node_cleaner::report::export::ExportFormat;
+use node_cleaner::report::packages::PackageSort;
+use node_cleaner::report::projects::ProjectSort;
+use node_cleaner::utils::age::parse_age;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::console::ConsoleObserver;
+
+#[derive(Debug, Clone, Copy)]
+pub struct InfoLevel;
+impl LogLevel for InfoLevel {
+    fn default() -> Option<Level> {
+        Some(Level::Info)
+    }
+}
+
+/// How a run reports its progress, see `--progress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ProgressFormat {
+    // Spinners and log lines on stderr
+    #[default]
+    Spinner,
+    // One JSON event per line on stderr, see `JsonObserver`
+    Json,
+    None,
+}
+
+impl ProgressFormat {
+    pub fn observer(self) -> Arc<dyn ScanObserver> {
+        match self {
+            ProgressFormat::Spinner => Arc::new(ConsoleObserver::default()),
+            ProgressFormat::Json => Arc::new(JsonObserver::stderr()),
+            ProgressFormat::None => Arc::new(SilentObserver),
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Cli {
+    // Make arguments optional
+    #[arg(short, long, global = true, required = false)]
+    pub debug: Option<bool>,
+
+    #[arg(short, long, required = false)]
+    pub tui: Option<bool>,
+
+    #[arg(short, long, global = true, required = false)]
+    pub full: bool,
+
+    /// Directories to scan (defaults to `/` when no root is given)
+    #[arg(value_name = "ROOT", required = false)]
+    pub paths: Vec<PathBuf>,
+
+    /// Additional directory to scan, can be repeated
+    #[arg(long = "root", value_name = "DIR", global = true, required = false)]
+    pub roots: Vec<PathBuf>,
+
+    /// Pattern config file, overrides the XDG and /etc config lookup
+    #[arg(short, long, value_name = "FILE", global = true, required = false)]
+    pub config: Option<PathBuf>,
+
+    /// Most risky rule tier to apply, every tier up to it is included (defaults to `$default`)
+    #[arg(short, long, value_enum, global = true, required = false)]
+    pub level: Option<Tier>,
+
+    /// Skip paths matching this rule (`prefix:`, `component:` or `glob:`), can be repeated
+    #[arg(short, long, value_name = "RULE", global = true, required = false)]
+    pub exclude: Vec<String>,
+
+    /// Read exclude rules from a file, one rule per line
+    #[arg(long, value_name = "FILE", global = true, required = false)]
+    pub exclude_from: Vec<PathBuf>,
+
+    /// Show which rule decides whether a path is removed, then exit
+    #[arg(long, value_name = "PATH", required = false)]
+    pub explain: Option<PathBuf>,
+
+    /// Print the active exclude rules and exit
+    #[arg(long, required = false)]
+    pub show_excludes: bool,
+
+    /// Export the full scan result (or a report) instead of removing anything
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        global = true,
+        required = false
+    )]
+    pub format: Option<ExportFormat>,
+
+    /// File to write the export to (defaults to stdout), implies `--format json`
+    #[arg(long, value_name = "FILE", global = true, required = false)]
+    pub output: Option<PathBuf>,
+
+    /// Walker threads shared between the roots (defaults to one per core)
+    #[arg(long, value_name = "N", global = true, required = false)]
+    pub threads: Option<usize>,
+
+    /// Follow symbolic links while walking and matching
+    #[arg(long, global = true, required = false)]
+    pub follow_symlinks: bool,
+
+    /// How progress is reported: spinners, JSON events on stderr, or nothing
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        global = true,
+        default_value_t = ProgressFormat::Spinner
+    )]
+    pub progress: ProgressFormat,
+
+    /// Read every node_modules again instead of reusing unchanged ones from
+    /// the index of earlier scans, and leave the index as it is
+    #[arg(long, global = true, required = false)]
+    pub no_cache: bool,
+
+    /// Move matches into a restorable trash session instead of deleting them
+    #[arg(long, global = true, required = false)]
+    pub trash: bool,
+
+    /// Trash directory, must be on the same filesystem as the matches
+    /// (defaults to `$XDG_DATA_HOME/node-cleaner/trash`)
+    #[arg(long, value_name = "DIR", global = true, required = false)]
+    pub trash_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub verbose: Verbosity<InfoLevel>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Scan and write every removal candidate to a plan file, nothing is deleted
+    Plan {
+        /// Where to write the plan
+        #[arg(short, long, value_name = "FILE")]
+        out: PathBuf,
+
+        /// Directories to scan (defaults to `/` when no root is given)
+        #[arg(value_name = "ROOT", required = false)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Delete exactly the entries of a reviewed plan file
+    Apply {
+        /// Plan file written by `plan`
+        #[arg(value_name = "PLAN")]
+        plan: PathBuf,
+
+        /// Do not ask for confirmation
+        #[arg(short, long, required = false)]
+        yes: bool,
+    },
+
+    /// Rank node_modules trees by how long their project has been untouched
+    /// and offer to remove the stale ones entirely
+    PruneProjects {
+        /// Projects untouched for at least this long are offered, e.g. `90d` or `12w`
+        #[arg(long, value_name = "AGE", value_parser = parse_age, default_value = "90d")]
+        stale: Duration,
+
+        /// Also offer projects without any readable mtime, their age is unknown
+        #[arg(long, required = false)]
+        include_undated: bool,
+
+        /// Directories to scan (defaults to `/` when no root is given)
+        #[arg(value_name = "ROOT", required = false)]
+        paths: Vec<PathBuf>,
+
+        /// Do not ask for confirmation
+        #[arg(short, long, required = false)]
+        yes: bool,
+    },
+
+    /// Break the scan result down without removing anything
+    Report {
+        #[command(subcommand)]
+        report: ReportCommand,
+    },
+
+    /// Move the paths of a trash session back, lists the sessions when none is given
+    Restore {
+        #[arg(value_name = "SESSION", required = false)]
+        session: Option<String>,
+    },
+
+    /// Permanently delete trash sessions
+    Purge {
+        /// Only purge sessions older than this, e.g. `12h`, `7d` or `2w`
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        older_than: Duration,
+    },
+
+    /// Inspect the index of earlier scans
+    Index {
+        #[command(subcommand)]
+        index: IndexCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IndexCommand {
+    /// What the index holds and how much the last scan reused
+    Stats,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReportCommand {
+    /// node_modules size, reclaimable bytes per tier, file count and last change per project
+    Projects {
+        /// Column to sort by, sizes and times sort largest/newest first
+        #[arg(short, long, value_enum, default_value = "size")]
+        sort: ProjectSort,
+
+        /// Flip the sort order
+        #[arg(short, long, required = false)]
+        reverse: bool,
+
+        /// Only show the first N rows
+        #[arg(long, value_name = "N", required = false)]
+        top: Option<usize>,
+
+        /// Directories to scan (defaults to `/` when no root is given)
+        #[arg(value_name = "ROOT", required = false)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Matched files and reclaimable bytes per npm package, summed over every project
+    Packages {
+        /// Column to sort by, counts and sizes sort largest first
+        #[arg(short, long, value_enum, default_value = "reclaimable")]
+        sort: PackageSort,
+
+        /// Flip the sort order
+        #[arg(short, long, required = false)]
+        reverse: bool,
+
+        /// Only show the first N rows
+        #[arg(long, value_name = "N", required = false)]
+        top: Option<usize>,
+
+        /// Directories to scan (defaults to `/` when no root is given)
+        #[arg(value_name = "ROOT", required = false)]
+        paths: Vec<PathBuf>,
+    },
+}
+
+impl Cli {
+    /// The active exclude rules: the defaults (unless `--full`), then the
+    /// config's `$excludes`, then every `--exclude-from` file, then every
+    /// `--exclude` flag
+    pub fn excludes(&self, config: &Config, source: &ConfigSource) -> Result<ExcludeSet, String> {
+        let defaults = if self.full {
+            ExcludeSet::default()
+        } else {
+            ExcludeSet::defaults()
+        };
+        let mut excludes = defaults.with_config(config, source)?;
+        for file in &self.exclude_from {
+            excludes = excludes.with_file(file)?;
+        }
+        for raw in &self.exclude {
+            excludes = excludes.with_rule(raw, ExcludeSource::Cli)?;
+        }
+        Ok(excludes)
+    }
+
+    /// `--format`, or JSON when only `--output` is given
+    pub fn export_format(&self) -> Option<ExportFormat> {
+        self.format
+            .or_else(|| self.output.as_ref().map(|_| ExportFormat::Json))
+    }
+
+    pub fn trash_dir(&self) -> PathBuf {
+        self.trash_dir.clone().unwrap_or_else(default_trash_dir)
+    }
+
+    /// How confirmed matches are removed, `--trash` or `--trash-dir` quarantine them
+    pub fn removal_mode(&self) -> RemovalMode {
+        if self.trash || self.trash_dir.is_some() {
+            RemovalMode::Trash(self.trash_dir())
+        } else {
+            RemovalMode::Delete
+        }
+    }
+
+    /// All scan roots given on the command line, positional ones first.
+    /// Falls back to `/` when none were given.
+    pub fn scan_roots(&self) -> Vec<PathBuf> {
+        let command_paths: &[PathBuf] = match &self.command {
+            Some(Command::Plan { paths, .. })
+            | Some(Command::PruneProjects { paths, .. })
+            | Some(Command::Report {
+                report:
+                    ReportCommand::Projects { paths, .. } | ReportCommand::Packages { paths, .. },
+            }) => paths,
+            _ => &[],
+        };
+        let mut roots: Vec<PathBuf> = self
+            .paths
+            .iter()
+            .chain(command_paths)
+            .chain(&self.roots)
+            .cloned()
+            .collect();
+        if roots.is_empty() {
+            roots.push(PathBuf::from("/"));
+        }
+        roots
+    }
+}
+
+pub fn setup_logger(cli: &Cli) {
+    let mp = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(cli.verbose.log_level_filter())
+        .format(|buf, record| {
+            let mut style = buf.style();
+            match record.level() {
+                Level::Error => style.set_color(Color::Red),
+                Level::Warn => style.set_color(Color::Yellow),
+                Level::Info => style.set_color(Color::Green),
+                Level::Debug => style.set_color(Color::Blue),
+                Level::Trace => style.set_color(Color::Cyan),
+            };
+            writeln!(buf, "{} - {}", style.value(record.level()), record.args())
+        });
+
+    // Wrap your logger with indicatif-aware bridge
+    LogWrapper::new(mp.clone(), Box::new(builder.build()))
+        .try_init()
+        .expect("Failed to initialize logger");
+}
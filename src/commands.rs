@@ -0,0 +1,431 @@
+// What each command line does with the `node_cleaner` library
+use log::{debug, info, warn};
+use node_cleaner::config::config::{ConfigSource, Tier, load_config};
+use node_cleaner::config::excludes::ExcludeSet;
+use node_cleaner::file_utils::fs_utils::WalkResult;
+use node_cleaner::file_utils::index::{self, ScanIndex};
+use node_cleaner::file_utils::matcher::PatternMatch;
+use node_cleaner::file_utils::plan::{Plan, PlanCheck, apply_entries};
+use node_cleaner::file_utils::rules::RuleMatcher;
+use node_cleaner::file_utils::trash;
+use node_cleaner::file_utils::{prune, remover};
+use node_cleaner::observer::{MultiObserver, ScanObserver};
+use node_cleaner::report::export::ExportObserver;
+use node_cleaner::report::{self, packages, projects};
+use node_cleaner::utils::age::format_age;
+use node_cleaner::utils::read_size::bytes_to_mb;
+use node_cleaner::{ScanError, Scanner};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cli::{Cli, Command, IndexCommand, ReportCommand};
+use crate::console::{SpinnerTheme, ask_yes_no, get_ticks, start_spinner, stop_spinner};
+use crate::tui;
+
+/// Runs whatever the command line asks for. Err is a run that could not
+/// start, otherwise every path that failed along the way is returned.
+pub fn run(cli: &Cli) -> Result<Vec<ScanError>, String> {
+    let start = Instant::now();
+
+    // Plans and the trash don't need the rules that filled them
+    match &cli.command {
+        Some(Command::Apply { plan, yes }) => return apply(cli, plan, *yes),
+        Some(Command::Restore { session }) => {
+            return restore(&cli.trash_dir(), session.as_deref());
+        }
+        Some(Command::Purge { older_than }) => return purge(&cli.trash_dir(), *older_than),
+        _ => {}
+    }
+
+    let (config, config_source) = load_config(cli.config.as_deref()).map_err(|e| e.to_string())?;
+    debug!("Using pattern config from {}", config_source);
+
+    let excludes = Arc::new(cli.excludes(&config, &config_source)?);
+
+    let level = cli
+        .level
+        .map(Ok)
+        .unwrap_or_else(|| config.default_tier())
+        .map_err(|e| format!("{} in {}", e, config_source))?;
+    debug!("Applying rule tiers up to `{}`", level);
+
+    let rules =
+        RuleMatcher::new(&config, level).map_err(|e| format!("{} in {}", e, config_source))?;
+
+    if cli.show_excludes {
+        excludes
+            .print(&mut io::stdout().lock())
+            .map_err(stdout_error)?;
+        return Ok(Vec::new());
+    }
+
+    if let Some(Command::Index {
+        index: IndexCommand::Stats,
+    }) = &cli.command
+    {
+        return index_stats(cli, &rules, &excludes);
+    }
+
+    if let Some(path) = &cli.explain {
+        explain(path, &excludes, &rules).map_err(stdout_error)?;
+        return Ok(Vec::new());
+    }
+
+    let mut scanner = Scanner::new(rules)
+        .roots(cli.scan_roots())
+        .excludes(Arc::clone(&excludes))
+        .follow_symlinks(cli.follow_symlinks);
+    if let Some(threads) = cli.threads {
+        scanner = scanner.threads(threads);
+    }
+    if !cli.no_cache {
+        scanner = scanner.index(index::default_index_path());
+    }
+
+    // The TUI draws its own progress
+    if cli.tui.unwrap_or(false) && cli.command.is_none() {
+        return tui::engine::run_tui(scanner, cli.removal_mode(), cli.debug.unwrap_or(false))
+            .map_err(|e| format!("TUI error: {:?}", e));
+    }
+
+    let observer = cli.progress.observer();
+
+    // Pruning sizes every node_modules through the same scan, the index
+    // included, and leaves the matches alone
+    if let Some(Command::PruneProjects {
+        stale,
+        include_undated,
+        yes,
+        ..
+    }) = &cli.command
+    {
+        let mut result = scanner.observer(Arc::clone(&observer)).scan();
+        let prune_errors = prune(cli, &result.walk, *stale, *include_undated, *yes, observer)?;
+        result.errors.extend(prune_errors);
+        return Ok(result.errors);
+    }
+
+    scan(cli, scanner, level, &config_source, observer, start)
+}
+
+// Scans, then reports, exports, plans or removes what was found
+fn scan(
+    cli: &Cli,
+    scanner: Scanner,
+    level: Tier,
+    config_source: &ConfigSource,
+    observer: Arc<dyn ScanObserver>,
+    start: Instant,
+) -> Result<Vec<ScanError>, String> {
+    // An export is filled in by the scan itself, next to the progress
+    let export = match (&cli.command, cli.export_format()) {
+        (Some(Command::Report { .. }), _) | (_, None) => None,
+        (_, Some(format)) => Some((
+            format,
+            Arc::new(ExportObserver::new(level, config_source.to_string())),
+        )),
+    };
+    let scanner = match &export {
+        Some((_, exporter)) => scanner.observer(Arc::new(MultiObserver::new(vec![
+            Arc::clone(&observer),
+            Arc::clone(exporter) as Arc<dyn ScanObserver>,
+        ]))),
+        None => scanner.observer(Arc::clone(&observer)),
+    };
+
+    let mut result = scanner.scan();
+    let elapsed = start.elapsed();
+
+    debug!("Found {} files\n", result.matches.len());
+
+    // Show up to 10 entries from the files collection
+    let entries_to_show = std::cmp::min(10, result.matches.len());
+    info!("Showing first {} file entries:", entries_to_show);
+    for (i, file) in result.matches.iter().take(entries_to_show).enumerate() {
+        info!("  {}. [{}] {}", i + 1, file.tier, file.path.display());
+    }
+    eprintln!("\n");
+    info!("Total execution time: {:.2?}", elapsed);
+
+    if let Some(Command::Report { report }) = &cli.command {
+        run_report(report, cli, level, &result.walk, &result.matches)?;
+        return Ok(result.errors);
+    }
+
+    if let Some((format, exporter)) = export {
+        let export = exporter
+            .take()
+            .ok_or("The scan finished without reporting its walk")?;
+        export
+            .write(format, cli.output.as_deref())
+            .map_err(|e| format!("Failed to write export: {}", e))?;
+        if let Some(output) = &cli.output {
+            info!(
+                "Wrote {} matches to {}",
+                result.matches.len(),
+                output.display()
+            );
+        }
+        return Ok(result.errors);
+    }
+
+    if let Some(Command::Plan { out, .. }) = &cli.command {
+        let (plan, plan_errors) = Plan::new(
+            level,
+            config_source.to_string(),
+            scanner.root_paths().to_vec(),
+            &result.matches,
+        );
+        plan.write(out).map_err(|e| e.to_string())?;
+        plan.log_summary();
+        info!(
+            "Wrote plan to {}, review it and run `apply {}`",
+            out.display(),
+            out.display()
+        );
+        result.errors.extend(plan_errors);
+        return Ok(result.errors);
+    }
+
+    let removal_errors = remove(cli, std::mem::take(&mut result.matches), observer);
+    result.errors.extend(removal_errors);
+    Ok(result.errors)
+}
+
+// Debug runs never remove anything
+fn debug_mode(cli: &Cli) -> bool {
+    let debug = cli.debug.unwrap_or(false);
+    if debug {
+        warn!("Debug mode is ON. No files will be deleted.");
+    }
+    debug
+}
+
+fn confirmed(cli: &Cli, assume_yes: bool) -> bool {
+    if assume_yes || ask_yes_no(cli.removal_mode().confirm_prompt()) {
+        return true;
+    }
+    warn!("User aborted deletion.");
+    false
+}
+
+// Removes the files the rules matched, after asking. Returns every path that
+// could not be removed.
+fn remove(
+    cli: &Cli,
+    mut matches: Vec<PatternMatch>,
+    observer: Arc<dyn ScanObserver>,
+) -> Vec<ScanError> {
+    remover::log_summary(&matches);
+    if debug_mode(cli) {
+        return Vec::new();
+    }
+
+    // Danger matches need their own confirmation on top of the general one
+    let danger_count = matches.iter().filter(|m| m.tier == Tier::Danger).count();
+    if danger_count > 0
+        && !ask_yes_no(&format!(
+            "{} paths matched `danger` rules and may break packages. Include them?",
+            danger_count
+        ))
+    {
+        warn!("Skipping {} `danger` matches.", danger_count);
+        matches.retain(|m| m.tier != Tier::Danger);
+    }
+
+    if !confirmed(cli, false) {
+        return Vec::new();
+    }
+    remover::remove_matches(matches, &cli.removal_mode(), observer)
+}
+
+// Prints a report, or writes it with `--format`/`--output`
+fn run_report(
+    report: &ReportCommand,
+    cli: &Cli,
+    level: Tier,
+    walk: &WalkResult,
+    matches: &[PatternMatch],
+) -> Result<(), String> {
+    let format = cli.export_format();
+
+    let result = match report {
+        ReportCommand::Projects {
+            sort, reverse, top, ..
+        } => {
+            let mut rows = projects::project_rows(&walk.node_modules, &walk.trees, matches);
+            let total = rows.len();
+            projects::sort_rows(&mut rows, *sort, *reverse);
+            rows.truncate(top.unwrap_or(total));
+            match format {
+                Some(format) => report::write_rows(&rows, format, cli.output.as_deref()),
+                None => projects::print_rows(&mut io::stdout().lock(), &rows, level, total),
+            }
+        }
+        ReportCommand::Packages {
+            sort, reverse, top, ..
+        } => {
+            let mut rows = packages::package_rows(matches);
+            let total = rows.len();
+            packages::sort_rows(&mut rows, *sort, *reverse);
+            rows.truncate(top.unwrap_or(total));
+            match format {
+                Some(format) => report::write_rows(&rows, format, cli.output.as_deref()),
+                None => packages::print_rows(&mut io::stdout().lock(), &rows, level, total),
+            }
+        }
+    };
+
+    result.map_err(|e| format!("Failed to write report: {}", e))
+}
+
+// Removes exactly what a reviewed plan lists
+fn apply(cli: &Cli, plan: &Path, yes: bool) -> Result<Vec<ScanError>, String> {
+    let plan = Plan::read(plan).map_err(|e| e.to_string())?;
+    plan.log_summary();
+
+    // Entries that vanished or changed since the plan was written are kept,
+    // and make the run end as partly failed
+    let PlanCheck {
+        ready,
+        drifted,
+        mut errors,
+    } = plan.check();
+    errors.extend(drifted.iter().map(|(entry, drift)| ScanError::Drifted {
+        path: entry.path.clone(),
+        reason: drift.to_string(),
+    }));
+    if ready.is_empty() {
+        info!("Nothing left to remove.");
+        return Ok(errors);
+    }
+    if debug_mode(cli) || !confirmed(cli, yes) {
+        return Ok(errors);
+    }
+
+    let (removed, removal_errors) =
+        apply_entries(&ready, &cli.removal_mode(), cli.progress.observer());
+    errors.extend(removal_errors);
+    info!(
+        "Removed {} of {} planned entries",
+        removed,
+        plan.entries.len()
+    );
+    Ok(errors)
+}
+
+// Ranks every node_modules by the age of its project and offers to remove
+// the stale ones. Returns the paths that could not be sized or removed.
+fn prune(
+    cli: &Cli,
+    walk: &WalkResult,
+    stale: Duration,
+    include_undated: bool,
+    yes: bool,
+    observer: Arc<dyn ScanObserver>,
+) -> Result<Vec<ScanError>, String> {
+    let now = SystemTime::now();
+    let spinner = start_spinner(
+        "Checking project activity...",
+        get_ticks(SpinnerTheme::SearchScan),
+    );
+    let (projects, mut errors) = prune::rank_projects(&walk.node_modules, &walk.trees);
+    stop_spinner(spinner, "Done checking project activity");
+    if projects.is_empty() {
+        info!("No node_modules directories found.");
+        return Ok(errors);
+    }
+
+    prune::print_projects(&mut io::stdout().lock(), &projects, stale, now).map_err(stdout_error)?;
+
+    let stale_projects = prune::stale_projects(&projects, stale, include_undated, now);
+    let stale_bytes: u64 = stale_projects.iter().map(|project| project.size).sum();
+    info!(
+        "{} of {} projects untouched for {} or more (marked *), {:.2} MB in node_modules",
+        stale_projects.len(),
+        projects.len(),
+        format_age(stale),
+        bytes_to_mb(stale_bytes)
+    );
+
+    if stale_projects.is_empty() || debug_mode(cli) || !confirmed(cli, yes) {
+        return Ok(errors);
+    }
+    errors.extend(prune::remove_projects(
+        &stale_projects,
+        &cli.removal_mode(),
+        observer,
+    ));
+    Ok(errors)
+}
+
+// Restores a trash session, or lists the sessions when none is given
+fn restore(trash_dir: &Path, session: Option<&str>) -> Result<Vec<ScanError>, String> {
+    let Some(session) = session else {
+        let sessions = trash::list_sessions(trash_dir).map_err(|e| e.to_string())?;
+        trash::print_sessions(&mut io::stdout().lock(), trash_dir, &sessions)
+            .map_err(stdout_error)?;
+        return Ok(Vec::new());
+    };
+
+    match trash::restore_session(trash_dir, session).map_err(|e| e.to_string())? {
+        (restored, 0) => info!("Restored {} paths from trash session {}", restored, session),
+        (restored, kept) => warn!(
+            "Restored {} paths from trash session {}, {} stay in the trash",
+            restored, session, kept
+        ),
+    }
+    Ok(Vec::new())
+}
+
+fn purge(trash_dir: &Path, older_than: Duration) -> Result<Vec<ScanError>, String> {
+    let purged = trash::purge_sessions(trash_dir, older_than).map_err(|e| e.to_string())?;
+    info!(
+        "Purged {} trash sessions older than {} ({:.2} MB)",
+        purged.sessions,
+        format_age(older_than),
+        bytes_to_mb(purged.bytes)
+    );
+    Ok(purged.errors)
+}
+
+fn index_stats(
+    cli: &Cli,
+    rules: &RuleMatcher,
+    excludes: &ExcludeSet,
+) -> Result<Vec<ScanError>, String> {
+    let path = index::default_index_path();
+    let scan_index = ScanIndex::read(&path).map_err(|e| e.to_string())?;
+    index::print_stats(
+        &mut io::stdout().lock(),
+        &path,
+        &scan_index,
+        index::fingerprint(rules, excludes, cli.follow_symlinks),
+    )
+    .map_err(stdout_error)?;
+    Ok(Vec::new())
+}
+
+// Tells which exclude rule and which pattern decide about `path`
+fn explain(path: &Path, excludes: &ExcludeSet, rules: &RuleMatcher) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if let Some((excluded, rule)) = excludes.explain(&path) {
+        writeln!(
+            out,
+            "Excluded: {} rule '{}' ({}) skips {}, the walker never visits this path",
+            rule.kind_name(),
+            rule.raw,
+            rule.source,
+            excluded.display()
+        )?;
+    }
+    rules.explain(&path).print(&mut out)
+}
+
+// Tables and explanations go to stdout, everything else is logged to stderr
+fn stdout_error(e: io::Error) -> String {
+    format!("Failed to write to stdout: {}", e)
+}
@@ -1,32 +1,271 @@
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::error::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Rules compiled into the binary, used when no config file is found
+pub const EMBEDDED_PATTERNS: &str = include_str!("./patterns.json");
+
+// Rule tiers, ordered from least to most risky
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Tier {
+    Safe,
+    Caution,
+    Danger,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 3] = [Tier::Safe, Tier::Caution, Tier::Danger];
+
+    /// Name of the tier as used for the rule set key in the config
+    pub fn name(self) -> &'static str {
+        match self {
+            Tier::Safe => "safe",
+            Tier::Caution => "caution",
+            Tier::Danger => "danger",
+        }
+    }
+
+    /// Every tier up to and including this one, safest first
+    pub fn up_to(self) -> impl Iterator<Item = Tier> {
+        Tier::ALL.into_iter().filter(move |tier| *tier <= self)
+    }
+
+    pub fn from_name(name: &str) -> Option<Tier> {
+        Tier::ALL
+            .into_iter()
+            .find(|tier| tier.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct RuleSet {
     pub patterns: Vec<String>,
-    #[allow(dead_code)]
+    // Package names (`name` or `@scope/name`) that are never cleaned
     pub ignore: Vec<String>,
 }
 
+// One field per tier rather than a flattened map, so a misspelled tier is
+// rejected and errors point at the value that is wrong
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(rename = "$default")]
-    #[allow(dead_code)]
     pub default: String,
-    #[serde(flatten)]
-    pub rules: HashMap<String, RuleSet>,
+    // Extra exclude rules, same syntax as `--exclude`
+    #[serde(rename = "$excludes", default)]
+    pub excludes: Vec<String>,
+    pub safe: Option<RuleSet>,
+    pub caution: Option<RuleSet>,
+    pub danger: Option<RuleSet>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct PaternHits {
-    pub patterns: Vec<String>,
+impl Config {
+    /// The tier named by `$default`
+    pub fn default_tier(&self) -> Result<Tier, String> {
+        Tier::from_name(&self.default)
+            .ok_or_else(|| format!("Unknown $default tier '{}'", self.default))
+    }
+
+    pub fn rule_set(&self, tier: Tier) -> Option<&RuleSet> {
+        match tier {
+            Tier::Safe => self.safe.as_ref(),
+            Tier::Caution => self.caution.as_ref(),
+            Tier::Danger => self.danger.as_ref(),
+        }
+    }
+
+    /// Rule sets for every tier up to `level`, safest first. Tiers missing
+    /// from the config are skipped.
+    pub fn rule_sets_up_to(&self, level: Tier) -> Vec<(Tier, &RuleSet)> {
+        level
+            .up_to()
+            .filter_map(|tier| self.rule_set(tier).map(|rules| (tier, rules)))
+            .collect()
+    }
+}
+
+// Where the active configuration was loaded from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    File(PathBuf),
+    Embedded,
+}
+
+impl ConfigSource {
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ConfigSource::File(path) => Some(path),
+            ConfigSource::Embedded => None,
+        }
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::File(path) => write!(f, "{}", path.display()),
+            ConfigSource::Embedded => write!(f, "<embedded patterns.json>"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        source: ConfigSource,
+        line: usize,
+        column: usize,
+        message: String,
+    },
 }
 
-// Function to read and parse patterns from the JSON file
-pub fn read_patterns() -> Result<Config, Box<dyn Error>> {
-    let data = include_str!("./patterns.json");
-    let config: Config = serde_json::from_str(data)?;
-    Ok(config)
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "Failed to read config {}: {}", path.display(), source)
+            }
+            ConfigError::Parse {
+                source,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Failed to parse config {}:{}:{}: {}",
+                source, line, column, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Config files tried in order when `--config` is not given:
+/// `$XDG_CONFIG_HOME/node-cleaner/config.json` (or `~/.config/...`),
+/// then `/etc/node-cleaner/config.json`
+pub fn config_search_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::with_capacity(2);
+
+    let user_config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = user_config_dir {
+        paths.push(dir.join("node-cleaner").join("config.json"));
+    }
+
+    paths.push(PathBuf::from("/etc/node-cleaner/config.json"));
+    paths
+}
+
+/// Parses a config from a JSON string, errors carry the line and column
+pub fn parse_config(data: &str, source: &ConfigSource) -> Result<Config, ConfigError> {
+    serde_json::from_str(data).map_err(|e| {
+        // serde_json appends " at line X column Y" to its message, we report it separately
+        let full = e.to_string();
+        let message = full
+            .rsplit_once(" at line ")
+            .map(|(message, _)| message.to_string())
+            .unwrap_or(full);
+        ConfigError::Parse {
+            source: source.clone(),
+            line: e.line(),
+            column: e.column(),
+            message,
+        }
+    })
+}
+
+fn read_config_file(path: &Path) -> Result<Config, ConfigError> {
+    let data = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_config(&data, &ConfigSource::File(path.to_path_buf()))
+}
+
+/// Loads the pattern config: the explicit `--config` file if given, else the
+/// first existing file from [`config_search_paths`], else the embedded rules.
+/// A file that exists but cannot be read or parsed is an error, it never
+/// silently falls back to the embedded rules.
+pub fn load_config(explicit: Option<&Path>) -> Result<(Config, ConfigSource), ConfigError> {
+    if let Some(path) = explicit {
+        let config = read_config_file(path)?;
+        return Ok((config, ConfigSource::File(path.to_path_buf())));
+    }
+
+    for path in config_search_paths() {
+        if path.is_file() {
+            let config = read_config_file(&path)?;
+            return Ok((config, ConfigSource::File(path)));
+        }
+    }
+
+    let config = parse_config(EMBEDDED_PATTERNS, &ConfigSource::Embedded)?;
+    Ok((config, ConfigSource::Embedded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> Result<Config, ConfigError> {
+        parse_config(data, &ConfigSource::Embedded)
+    }
+
+    #[test]
+    fn embedded_patterns_parse() {
+        let config = parse(EMBEDDED_PATTERNS).unwrap();
+        assert_eq!(config.default_tier(), Ok(Tier::Safe));
+        assert!(config.rule_set(Tier::Safe).is_some());
+    }
+
+    #[test]
+    fn type_errors_point_at_the_value() {
+        let data = r#"{
+  "$default": "safe",
+  "safe": {
+    "patterns": "test",
+    "ignore": []
+  }
+}"#;
+        match parse(data) {
+            Err(ConfigError::Parse { line, column, .. }) => assert_eq!((line, column), (4, 22)),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn misspelled_tiers_are_rejected() {
+        let data = r#"{ "$default": "safe", "cuation": { "patterns": [], "ignore": [] } }"#;
+        match parse(data) {
+            Err(ConfigError::Parse { message, .. }) => assert!(message.contains("cuation")),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_tiers_are_skipped() {
+        let data =
+            r#"{ "$default": "caution", "caution": { "patterns": ["*.md"], "ignore": [] } }"#;
+        let config = parse(data).unwrap();
+        let sets = config.rule_sets_up_to(Tier::Danger);
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].0, Tier::Caution);
+    }
 }
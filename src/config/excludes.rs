@@ -0,0 +1,322 @@
+use log::debug;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::config::{Config, ConfigSource};
+use crate::utils::glob::{Glob, has_glob_meta};
+
+// Built-in exclusions, dropped by `--full`
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "prefix:/proc/",
+    "prefix:/sys/",
+    "prefix:/dev/",
+    "prefix:/run/",
+    "prefix:/efi/",
+    "prefix:/usr/",
+    "prefix:/mnt/",
+    "prefix:/opt/",
+    "component:.vscode",
+];
+
+#[derive(Debug, Clone)]
+pub enum ExcludeKind {
+    // Absolute path prefix compared by whole components, `/proc/` also covers
+    // `/proc` itself but not `/process`
+    Prefix(String),
+    // Any path component with exactly this name
+    Component(String),
+    // Glob over the absolute path, a relative glob matches at any depth
+    Glob(Glob),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExcludeSource {
+    Default,
+    Cli,
+    // Added through `Scanner::exclude`
+    Scanner,
+    File(PathBuf),
+    Config(ConfigSource),
+}
+
+impl fmt::Display for ExcludeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExcludeSource::Default => write!(f, "default"),
+            ExcludeSource::Cli => write!(f, "--exclude"),
+            ExcludeSource::Scanner => write!(f, "Scanner::exclude"),
+            ExcludeSource::File(path) => write!(f, "{}", path.display()),
+            ExcludeSource::Config(source) => write!(f, "{}", source),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExcludeRule {
+    pub raw: String,
+    pub kind: ExcludeKind,
+    pub source: ExcludeSource,
+    hits: AtomicUsize,
+}
+
+// A copy starts with the hits counted so far
+impl Clone for ExcludeRule {
+    fn clone(&self) -> Self {
+        Self {
+            raw: self.raw.clone(),
+            kind: self.kind.clone(),
+            source: self.source.clone(),
+            hits: AtomicUsize::new(self.hits()),
+        }
+    }
+}
+
+impl ExcludeRule {
+    /// Parses `prefix:<path>`, `component:<name>` or `glob:<pattern>`.
+    /// Without a prefix, rules containing glob characters are globs, rules
+    /// starting with `/` are prefixes and everything else is a component.
+    pub fn parse(raw: &str, source: ExcludeSource) -> Result<Self, String> {
+        let raw = raw.trim();
+        let kind = if let Some(prefix) = raw.strip_prefix("prefix:") {
+            ExcludeKind::Prefix(prefix.to_string())
+        } else if let Some(name) = raw.strip_prefix("component:") {
+            ExcludeKind::Component(name.to_string())
+        } else if let Some(pattern) = raw.strip_prefix("glob:") {
+            ExcludeKind::Glob(compile_glob(pattern)?)
+        } else if has_glob_meta(raw) {
+            ExcludeKind::Glob(compile_glob(raw)?)
+        } else if raw.starts_with('/') {
+            ExcludeKind::Prefix(raw.to_string())
+        } else {
+            ExcludeKind::Component(raw.to_string())
+        };
+
+        let empty = match &kind {
+            ExcludeKind::Prefix(value) | ExcludeKind::Component(value) => value.is_empty(),
+            ExcludeKind::Glob(_) => false,
+        };
+        if empty {
+            return Err(format!("Empty exclude rule '{}'", raw));
+        }
+
+        Ok(Self {
+            raw: raw.to_string(),
+            kind,
+            source,
+            hits: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            ExcludeKind::Prefix(_) => "prefix",
+            ExcludeKind::Component(_) => "component",
+            ExcludeKind::Glob(_) => "glob",
+        }
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    fn matches(&self, path: &Path, segments: &[&str]) -> bool {
+        match &self.kind {
+            ExcludeKind::Prefix(prefix) => path.starts_with(prefix),
+            ExcludeKind::Component(name) => path
+                .components()
+                .any(|c| matches!(c, Component::Normal(n) if n == name.as_str())),
+            ExcludeKind::Glob(glob) => glob.match_prefix_len(segments).is_some(),
+        }
+    }
+}
+
+// Relative globs are allowed to match anywhere in the path
+fn compile_glob(pattern: &str) -> Result<Glob, String> {
+    let anchored = if pattern.starts_with('/') || pattern.starts_with("**") {
+        pattern.to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    Glob::new(&anchored).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExcludeSet {
+    rules: Vec<ExcludeRule>,
+}
+
+impl ExcludeSet {
+    pub fn new(rules: Vec<ExcludeRule>) -> Self {
+        Self { rules }
+    }
+
+    /// The built-in exclusions, see `DEFAULT_EXCLUDES`
+    pub fn defaults() -> Self {
+        let rules = DEFAULT_EXCLUDES
+            .iter()
+            .map(|raw| ExcludeRule::parse(raw, ExcludeSource::Default))
+            .collect::<Result<_, _>>()
+            .expect("built-in exclude rules are valid");
+        Self::new(rules)
+    }
+
+    /// Adds one rule in the `--exclude` syntax, see `ExcludeRule::parse`
+    pub fn with_rule(mut self, raw: &str, source: ExcludeSource) -> Result<Self, String> {
+        self.rules.push(ExcludeRule::parse(raw, source)?);
+        Ok(self)
+    }
+
+    /// Adds the config's `$excludes`
+    pub fn with_config(mut self, config: &Config, source: &ConfigSource) -> Result<Self, String> {
+        for raw in &config.excludes {
+            let rule = ExcludeRule::parse(raw, ExcludeSource::Config(source.clone()))
+                .map_err(|e| format!("{}: {}", source, e))?;
+            self.rules.push(rule);
+        }
+        Ok(self)
+    }
+
+    /// Adds every rule of an exclude file: one rule per line, `#` starts a comment
+    pub fn with_file(mut self, path: &Path) -> Result<Self, String> {
+        self.rules.extend(read_exclude_file(path)?);
+        Ok(self)
+    }
+
+    pub fn rules(&self) -> &[ExcludeRule] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // First rule that excludes `path` itself, without counting the hit
+    fn find(&self, path: &Path) -> Option<&ExcludeRule> {
+        if self.rules.is_empty() {
+            return None;
+        }
+
+        let path_str = path.to_string_lossy();
+        let segments: Vec<&str> = path_str.split('/').filter(|s| !s.is_empty()).collect();
+        self.rules.iter().find(|rule| rule.matches(path, &segments))
+    }
+
+    /// Returns the first rule that excludes `path` and counts the hit
+    pub fn matching_rule(&self, path: &Path) -> Option<&ExcludeRule> {
+        let rule = self.find(path)?;
+        rule.hits.fetch_add(1, Ordering::Relaxed);
+        debug!(
+            "Excluded {} ({} rule '{}' from {})",
+            path.display(),
+            rule.kind_name(),
+            rule.raw,
+            rule.source
+        );
+        Some(rule)
+    }
+
+    /// Why the walker never reaches `path`: the outermost directory on the
+    /// way down to it that a rule excludes, and that rule. Hits are not counted.
+    pub fn explain<'a>(&self, path: &'a Path) -> Option<(&'a Path, &ExcludeRule)> {
+        let mut ancestors: Vec<&Path> = path.ancestors().collect();
+        ancestors.reverse();
+        ancestors
+            .into_iter()
+            .find_map(|ancestor| Some((ancestor, self.find(ancestor)?)))
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.matching_rule(path).is_some()
+    }
+
+    /// Writes the active rules to `out`, used by `--show-excludes`
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.rules.is_empty() {
+            return writeln!(out, "No exclude rules active");
+        }
+
+        writeln!(out, "{:<10} {:<40} SOURCE", "KIND", "RULE")?;
+        for rule in &self.rules {
+            writeln!(
+                out,
+                "{:<10} {:<40} {}",
+                rule.kind_name(),
+                rule.raw,
+                rule.source
+            )?;
+        }
+        writeln!(
+            out,
+            "\nRun with --explain <PATH> to see which rule skips a path, or -v to log every skipped path"
+        )
+    }
+}
+
+// Reads an exclude file: one rule per line, `#` starts a comment
+fn read_exclude_file(path: &Path) -> Result<Vec<ExcludeRule>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read exclude file {}: {}", path.display(), e))?;
+
+    let mut rules = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let rule = ExcludeRule::parse(line, ExcludeSource::File(path.to_path_buf()))
+            .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+        rules.push(rule);
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excludes(raw: &[&str]) -> ExcludeSet {
+        ExcludeSet::new(
+            raw.iter()
+                .map(|raw| ExcludeRule::parse(raw, ExcludeSource::Cli).unwrap())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn prefixes_match_whole_components() {
+        let set = excludes(&["/home/u/work"]);
+        assert!(set.is_excluded(Path::new("/home/u/work")));
+        assert!(set.is_excluded(Path::new("/home/u/work/app/node_modules")));
+        assert!(!set.is_excluded(Path::new("/home/u/workspace")));
+        assert!(!set.is_excluded(Path::new("/home/u/workspace/app")));
+        assert!(!set.is_excluded(Path::new("/home/u")));
+    }
+
+    #[test]
+    fn explain_names_the_outermost_excluded_directory() {
+        let set = excludes(&["component:vendor", "/srv/cache"]);
+        let (path, rule) = set
+            .explain(Path::new("/srv/app/vendor/lib/node_modules"))
+            .unwrap();
+        assert_eq!(path, Path::new("/srv/app/vendor"));
+        assert_eq!(rule.raw, "component:vendor");
+
+        let (path, rule) = set.explain(Path::new("/srv/cache/vendor")).unwrap();
+        assert_eq!(path, Path::new("/srv/cache"));
+        assert_eq!(rule.raw, "/srv/cache");
+
+        assert!(set.explain(Path::new("/srv/app/src")).is_none());
+        assert_eq!(set.rules()[0].hits(), 0);
+    }
+
+    #[test]
+    fn trailing_slash_prefixes_cover_the_directory_itself() {
+        let set = excludes(&["prefix:/proc/"]);
+        assert!(set.is_excluded(Path::new("/proc")));
+        assert!(set.is_excluded(Path::new("/proc/1/cwd")));
+        assert!(!set.is_excluded(Path::new("/process")));
+    }
+}
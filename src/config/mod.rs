@@ -1,3 +1,3 @@
-pub mod cli;
 #[allow(clippy::module_inception)]
 pub mod config;
+pub mod excludes;
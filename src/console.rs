@@ -0,0 +1,195 @@
+// What the CLI draws on the terminal: spinners, prompts and the progress of a scan
+use dialoguer::theme::ColorfulTheme;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn};
+use node_cleaner::ScanObserver;
+use node_cleaner::config::excludes::ExcludeSet;
+use node_cleaner::file_utils::fs_utils::WalkResult;
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+pub static DIALOG_THEME: Lazy<ColorfulTheme> = Lazy::new(ColorfulTheme::default);
+
+pub enum SpinnerTheme {
+    FileWalker,
+    SearchScan,
+}
+
+#[rustfmt::skip]
+pub fn get_ticks(theme: SpinnerTheme) -> &'static [&'static str] {
+    match theme {
+        SpinnerTheme::FileWalker => &[
+            "📁", "📁", "📁",
+            "📂", "📂", "📂",
+            "📁", "📁", "📁",
+            "📂", "📂", "📂",
+        ],
+        SpinnerTheme::SearchScan => &[
+            "🔍", "🔍", "🔍",
+            "🔎", "🔎", "🔎",
+            "📡", "📡", "📡",
+            "📶", "📶", "📶",
+        ],
+    }
+}
+
+// Blank line between log sections on stderr
+pub fn spacer() {
+    eprintln!();
+}
+
+pub fn start_spinner(message: &str, ticks: &[&str]) -> ProgressBar {
+    let spinner = ProgressBar::new_spinner();
+
+    spinner.set_style(
+        ProgressStyle::with_template("{spinner} {msg}")
+            .unwrap()
+            .tick_strings(ticks),
+    );
+
+    spinner.set_message(message.to_string());
+    spinner.enable_steady_tick(Duration::from_millis(80));
+    spinner
+}
+
+pub fn stop_spinner(spinner: ProgressBar, final_message: &str) {
+    spinner.finish_and_clear(); // Clears spinner line completely
+    eprintln!("✔️  {}", final_message);
+}
+
+pub fn ask_yes_no(prompt: &str) -> bool {
+    dialoguer::Confirm::with_theme(&*DIALOG_THEME)
+        .with_prompt(prompt)
+        .default(true)
+        .interact()
+        .unwrap_or(false)
+}
+
+// Only every Nth directory is shown next to the spinner, setting the message
+// takes a lock
+const SPINNER_PATH_EVERY: usize = 256;
+
+/// What the CLI shows: a spinner per phase and the walk statistics as log lines
+#[derive(Debug, Default)]
+pub struct ConsoleObserver {
+    spinner: Mutex<Option<ProgressBar>>,
+    dirs: AtomicUsize,
+}
+
+impl ConsoleObserver {
+    fn start(&self, message: &str, theme: SpinnerTheme) {
+        spacer();
+        let spinner = start_spinner(message, get_ticks(theme));
+        *self.spinner.lock().unwrap() = Some(spinner);
+    }
+
+    fn stop(&self, message: &str) {
+        if let Some(spinner) = self.spinner.lock().unwrap().take() {
+            stop_spinner(spinner, message);
+        }
+        spacer();
+    }
+}
+
+impl ScanObserver for ConsoleObserver {
+    fn on_walk_started(&self, roots: &[PathBuf], threads: usize) {
+        info!(
+            "Using {:?} threads for traversal starting from {:?}",
+            threads, roots
+        );
+        self.start("Walking file tree...", SpinnerTheme::FileWalker);
+    }
+
+    fn on_dir_entered(&self, path: &Path) {
+        let dirs = self.dirs.fetch_add(1, Ordering::Relaxed);
+        if dirs.is_multiple_of(SPINNER_PATH_EVERY)
+            && let Some(spinner) = &*self.spinner.lock().unwrap()
+        {
+            spinner.set_message(format!("Walking file tree... {}", path.display()));
+        }
+    }
+
+    fn on_walk_finished(&self, walk: &WalkResult, excludes: &ExcludeSet) {
+        self.stop("Done walking");
+        log_walk_summary(walk, excludes);
+    }
+}
+
+// The statistics of a finished walk, in total, per root and per exclude rule
+fn log_walk_summary(walk: &WalkResult, excludes: &ExcludeSet) {
+    let elapsed = walk.elapsed;
+    let node_modules_count = walk.node_modules_found();
+
+    // Print benchmark results
+    info!("Traversal completed in {:.2?}", elapsed);
+    info!("Directories scanned: {}", walk.dirs());
+    info!("Files scanned: {}", walk.files());
+    info!("node_modules directories found: {}", node_modules_count);
+    info!("Paths ignored: {}", walk.ignored());
+    if !walk.errors.is_empty() {
+        warn!("Paths that could not be read: {}", walk.errors.len());
+    }
+    info!("Total entries processed: {}", walk.dirs() + walk.files());
+
+    // Per-root totals
+    if walk.roots.len() > 1 {
+        info!("Per-root totals:");
+        for summary in &walk.roots {
+            info!(
+                "  {} - {} dirs, {} files, {} node_modules, {} ignored in {:.2?}",
+                summary.root.display(),
+                summary.dirs,
+                summary.files,
+                summary.node_modules,
+                summary.ignored,
+                summary.elapsed
+            );
+        }
+    }
+
+    // Show which exclude rules were responsible for skipped paths
+    for rule in excludes.rules().iter().filter(|rule| rule.hits() > 0) {
+        info!(
+            "  excluded {} paths by {} rule '{}' ({})",
+            rule.hits(),
+            rule.kind_name(),
+            rule.raw,
+            rule.source
+        );
+    }
+
+    // Calculate and print processing speed
+    info!(
+        "Processing speed: {:.2} entries/sec",
+        walk.entries_per_sec()
+    );
+
+    // Calculate and print node_modules finding speed
+    let node_modules_speed = if elapsed.as_secs_f64() > 0.0 {
+        node_modules_count as f64 / elapsed.as_secs_f64()
+    } else {
+        node_modules_count as f64 // Avoid division by zero
+    };
+    info!(
+        "node_modules finding speed: {:.2} node_modules/sec",
+        node_modules_speed
+    );
+
+    // Print a sample of found node_modules locations
+    info!("Sample of node_modules locations found:\n");
+    let display_count = std::cmp::min(walk.node_modules.len(), 10); // Display up to 10 locations
+
+    for location in walk.node_modules.iter().take(display_count) {
+        info!("  - {}", location.display());
+    }
+
+    if walk.node_modules.len() > 10 {
+        info!(
+            "  ... and {} more \n",
+            walk.node_modules.len() - display_count
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error};
+
+use crate::file_utils::trash::TrashError;
+
+/// Exit code of a run that got to the end but failed on some paths.
+/// 1 is kept for runs that could not start at all, 2 for usage errors.
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+
+// Example paths listed per kind in the summary
+const SUMMARY_EXAMPLES: usize = 5;
+
+/// Why a single path could not be walked, sized, matched or removed. These
+/// are collected instead of aborting the run and summed up at the end.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanError {
+    PermissionDenied(PathBuf),
+    // Deleted or moved between being found and being read
+    Vanished(PathBuf),
+    // Trashing across filesystems, paths can only be renamed into the trash
+    CrossDevice(PathBuf),
+    // A followed symlink pointing back at one of its ancestors
+    SymlinkLoop(PathBuf),
+    // A planned entry that changed since the plan was written, kept
+    Drifted { path: PathBuf, reason: String },
+    // Unusable config or rules, not tied to a scanned path
+    Config(String),
+    // Anything else the OS reported
+    Io { path: PathBuf, message: String },
+}
+
+impl ScanError {
+    pub fn from_io(path: &Path, e: &io::Error) -> Self {
+        let path = path.to_path_buf();
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => ScanError::PermissionDenied(path),
+            io::ErrorKind::NotFound => ScanError::Vanished(path),
+            io::ErrorKind::CrossesDevices => ScanError::CrossDevice(path),
+            _ => ScanError::Io {
+                path,
+                message: e.to_string(),
+            },
+        }
+    }
+
+    pub fn from_walkdir(e: &walkdir::Error) -> Self {
+        Self::from_walk(e.path(), e.loop_ancestor().is_some(), e.io_error(), e)
+    }
+
+    pub fn from_jwalk(e: &jwalk::Error) -> Self {
+        Self::from_walk(e.path(), e.loop_ancestor().is_some(), e.io_error(), e)
+    }
+
+    // Both walkers report errors the same way
+    fn from_walk(
+        path: Option<&Path>,
+        looped: bool,
+        io: Option<&io::Error>,
+        e: &dyn fmt::Display,
+    ) -> Self {
+        let path = path.unwrap_or(Path::new(""));
+        match io {
+            _ if looped => ScanError::SymlinkLoop(path.to_path_buf()),
+            Some(io) => Self::from_io(path, io),
+            None => ScanError::Io {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            },
+        }
+    }
+
+    pub fn from_trash(e: &TrashError) -> Self {
+        match e {
+            TrashError::Io { path, source } => Self::from_io(path, source),
+            TrashError::CrossDevice { path, .. } => ScanError::CrossDevice(path.clone()),
+            TrashError::Manifest { path, source } => ScanError::Io {
+                path: path.clone(),
+                message: source.to_string(),
+            },
+            TrashError::UnknownSession(_) => ScanError::Io {
+                path: PathBuf::new(),
+                message: e.to_string(),
+            },
+        }
+    }
+
+    /// None for errors not tied to a path
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ScanError::PermissionDenied(path)
+            | ScanError::Vanished(path)
+            | ScanError::CrossDevice(path)
+            | ScanError::SymlinkLoop(path)
+            | ScanError::Drifted { path, .. }
+            | ScanError::Io { path, .. } => Some(path),
+            ScanError::Config(_) => None,
+        }
+    }
+
+    /// What went wrong, without the path. Errors are grouped by this.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ScanError::PermissionDenied(_) => "permission denied",
+            ScanError::Vanished(_) => "missing or vanished",
+            ScanError::CrossDevice(_) => "on another filesystem than the trash",
+            ScanError::SymlinkLoop(_) => "symlink loop",
+            ScanError::Drifted { .. } => "changed since the plan",
+            ScanError::Config(_) => "config error",
+            ScanError::Io { .. } => "I/O error",
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanError::Config(message) => write!(f, "{}: {}", self.kind(), message),
+            ScanError::Io { path, message } => write!(f, "{}: {}", path.display(), message),
+            ScanError::Drifted { path, reason } => write!(f, "{}: {}", path.display(), reason),
+            _ => write!(
+                f,
+                "{}: {}",
+                self.path().unwrap_or(Path::new("")).display(),
+                self.kind()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+/// Logs how many paths failed per kind, with a few examples each. Every
+/// error is logged at debug level.
+pub fn log_summary(errors: &[ScanError]) {
+    if errors.is_empty() {
+        return;
+    }
+
+    let mut by_kind: BTreeMap<&str, Vec<&ScanError>> = BTreeMap::new();
+    for e in errors {
+        debug!("{}", e);
+        by_kind.entry(e.kind()).or_default().push(e);
+    }
+
+    error!("Finished with {} errors:", errors.len());
+    for (kind, errors) in by_kind {
+        error!("  {}: {}", kind, errors.len());
+        for e in errors.iter().take(SUMMARY_EXAMPLES) {
+            match e {
+                ScanError::Config(message) => error!("    - {}", message),
+                ScanError::Io { path, message } => {
+                    error!("    - {} ({})", path.display(), message)
+                }
+                _ => error!("    - {}", e.path().unwrap_or(Path::new("")).display()),
+            }
+        }
+        if errors.len() > SUMMARY_EXAMPLES {
+            error!("    ... and {} more", errors.len() - SUMMARY_EXAMPLES);
+        }
+    }
+}
@@ -1,260 +1,656 @@
-use jwalk::WalkDirGeneric;
-use log::info;
-use std::cell::RefCell;
-use std::collections::HashSet;
-use std::path::PathBuf;
+use jwalk::{ClientState, WalkDirGeneric};
+use log::{debug, error, warn};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex, MutexGuard};
-use std::time::Instant;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::excludes::ExcludeSet;
+use crate::error::ScanError;
+use crate::file_utils::index::{IndexedNodeModules, ScanIndex};
+use crate::file_utils::matcher::{EntryFacts, MatchStats, NodeModulesTally, PatternMatch};
+use crate::file_utils::rules::RuleMatcher;
+use crate::observer::ScanObserver;
+use crate::utils::read_size::TreeStats;
+
+// Counters for a single scan root, updated concurrently by the walker
+#[derive(Debug, Default)]
+pub struct RootStats {
+    pub files: AtomicUsize,
+    pub dirs: AtomicUsize,
+    pub node_modules: AtomicUsize,
+    pub ignored: AtomicUsize,
+    pub errors: AtomicUsize,
+}
+
+// Only every Nth directory is published as the current path, so the
+// walker threads rarely contend on the lock
+const CURRENT_PATH_EVERY: usize = 64;
+
+/// Live view of a running walk, shared with whoever wants to display it
+#[derive(Debug)]
+pub struct WalkProgress {
+    started: Instant,
+    // The counters of every root being walked
+    roots: Mutex<Vec<Arc<RootStats>>>,
+    current: Mutex<Option<PathBuf>>,
+    // Set once the walk is done, stops the clock
+    elapsed: OnceLock<Duration>,
+}
+
+/// The counters of a `WalkProgress` at one point in time
+#[derive(Debug, Clone, Default)]
+pub struct WalkSnapshot {
+    pub elapsed: Duration,
+    pub dirs: usize,
+    pub files: usize,
+    pub node_modules: usize,
+    pub ignored: usize,
+    pub errors: usize,
+    // None before the first directory and after the walk
+    pub current: Option<PathBuf>,
+    pub done: bool,
+}
+
+impl WalkProgress {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            roots: Mutex::new(Vec::new()),
+            current: Mutex::new(None),
+            elapsed: OnceLock::new(),
+        }
+    }
 
-use super::matcher;
+    fn track(&self, stats: &Arc<RootStats>) {
+        self.roots.lock().unwrap().push(Arc::clone(stats));
+    }
 
-use crate::config::cli;
-use crate::utils::g_utils::{SpinnerTheme, get_ticks, start_spinner, stop_spinner};
+    fn visit(&self, path: &Path) {
+        *self.current.lock().unwrap() = Some(path.to_path_buf());
+    }
 
-// Thread-local storage for batching path operations
-thread_local! {
-    static LOCAL_NODE_MODULES: RefCell<Vec<String>> = RefCell::new(Vec::with_capacity(50));
+    fn finish(&self) {
+        let _ = self.elapsed.set(self.started.elapsed());
+        *self.current.lock().unwrap() = None;
+    }
+
+    pub fn snapshot(&self) -> WalkSnapshot {
+        let mut snapshot = WalkSnapshot {
+            elapsed: self
+                .elapsed
+                .get()
+                .copied()
+                .unwrap_or_else(|| self.started.elapsed()),
+            current: self.current.lock().unwrap().clone(),
+            done: self.elapsed.get().is_some(),
+            ..WalkSnapshot::default()
+        };
+        for stats in self.roots.lock().unwrap().iter() {
+            snapshot.dirs += stats.dirs.load(Ordering::Relaxed);
+            snapshot.files += stats.files.load(Ordering::Relaxed);
+            snapshot.node_modules += stats.node_modules.load(Ordering::Relaxed);
+            snapshot.ignored += stats.ignored.load(Ordering::Relaxed);
+            snapshot.errors += stats.errors.load(Ordering::Relaxed);
+        }
+        snapshot
+    }
 }
 
-// Optimized path ignoring function for jwalk
-pub fn is_ignored<C: jwalk::ClientState>(entry: &jwalk::DirEntry<C>) -> bool {
-    let path = entry.path();
-
-    if !(*cli::FULL_SCAN.lock().unwrap()) {
-        // Fast prefix check for common system directories
-        let path_str = path.as_os_str().to_string_lossy();
-        if path_str.starts_with("/proc/")
-            || path_str.starts_with("/sys/")
-            || path_str.starts_with("/dev/")
-            || path_str.starts_with("/run/")
-            || path_str.starts_with("/efi/")
-            || path_str.starts_with("/usr/")
-            || path_str.starts_with("/mnt/")
-        {
-            return true;
+impl Default for WalkProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WalkSnapshot {
+    pub fn entries_per_sec(&self) -> f64 {
+        let entries = (self.dirs + self.files) as f64;
+        if self.elapsed.as_secs_f64() > 0.0 {
+            entries / self.elapsed.as_secs_f64()
+        } else {
+            entries // Avoid division by zero
         }
+    }
+}
 
-        // Fast component check to avoid repeated iteration
-        let components_iter = path.components();
-        for component in components_iter {
-            if let std::path::Component::Normal(name) = component {
-                let name_str = name.to_string_lossy();
-                if name_str == "Projects" || name_str == "opt" || name_str == ".vscode" {
-                    return true;
-                }
+/// How the walker goes through the file tree
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WalkOptions {
+    // Walker threads shared between the roots, None uses every core
+    pub threads: Option<usize>,
+    pub follow_links: bool,
+}
+
+impl WalkOptions {
+    pub fn threads(&self) -> usize {
+        self.threads
+            .filter(|threads| *threads > 0)
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|p| p.get())
+                    .unwrap_or(4)
+            })
+    }
+}
+
+// Final totals for a single scan root
+#[derive(Debug, Clone)]
+pub struct RootSummary {
+    pub root: PathBuf,
+    pub files: usize,
+    pub dirs: usize,
+    pub node_modules: usize,
+    pub ignored: usize,
+    pub elapsed: Duration,
+}
+
+/// Replaces `path` with `data` through a temporary file next to it, so a
+/// crash never leaves a torn file behind. Every write gets its own temporary
+/// file, writers racing for the same path only race for the final rename.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(format!(
+        ".{}-{}.partial",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let partial = PathBuf::from(partial);
+    let written = fs::write(&partial, data).and_then(|()| fs::rename(&partial, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written
+}
+
+// Canonicalizes the requested roots, removes duplicates and drops every root
+// that is nested inside another one so no directory is walked twice. Roots
+// that cannot be walked come back as errors.
+pub fn normalize_roots(roots: &[PathBuf]) -> (Vec<PathBuf>, Vec<ScanError>) {
+    let mut errors = Vec::new();
+    let mut canonical: Vec<PathBuf> = roots
+        .iter()
+        .filter_map(|root| match root.canonicalize() {
+            Ok(path) if path.is_dir() => Some(path),
+            Ok(path) => {
+                warn!("Skipping root {}: not a directory", path.display());
+                errors.push(ScanError::Io {
+                    path,
+                    message: "not a directory".to_string(),
+                });
+                None
             }
+            Err(e) => {
+                warn!("Skipping root {}: {}", root.display(), e);
+                errors.push(ScanError::from_io(root, &e));
+                None
+            }
+        })
+        .collect();
+
+    // Sorting puts every parent directly before its children
+    canonical.sort();
+    canonical.dedup();
+
+    let mut kept: Vec<PathBuf> = Vec::with_capacity(canonical.len());
+    for root in canonical {
+        if let Some(parent) = kept.iter().find(|parent| root.starts_with(parent)) {
+            debug!(
+                "Dropping root {} because it is inside {}",
+                root.display(),
+                parent.display()
+            );
+            continue;
         }
+        kept.push(root);
     }
+    (kept, errors)
+}
 
-    false
+// Everything a walk produced: the node_modules found and the per-root counters
+#[derive(Debug, Clone, Default)]
+pub struct WalkResult {
+    pub node_modules: Vec<PathBuf>,
+    // node_modules inside another one, counted by `node_modules_found` but
+    // sized and removed with the outermost one
+    pub nested: Vec<PathBuf>,
+    // Only filled when the walk matched rules, `Scanner::scan` moves them
+    // into its result
+    pub matches: Vec<PatternMatch>,
+    // With an index: the node_modules served from it, and the ones read
+    // again with what to index for them
+    pub reused: Vec<PathBuf>,
+    pub indexed: Vec<(PathBuf, IndexedNodeModules)>,
+    // The totals of every node_modules the walk matched, as it tallied them
+    pub trees: Vec<(PathBuf, TreeStats)>,
+    pub roots: Vec<RootSummary>,
+    pub elapsed: Duration,
+    // Roots and entries that could not be read, the walk goes on without them
+    pub errors: Vec<ScanError>,
 }
 
-// Optimized function to convert string to PathBuf
-pub fn convert_string_to_pathbuf(mutex: &MutexGuard<Vec<String>>) -> Vec<PathBuf> {
-    let mut result = Vec::with_capacity(mutex.len());
-    for s in mutex.iter() {
-        result.push(PathBuf::from(s));
+impl WalkResult {
+    pub fn dirs(&self) -> usize {
+        self.roots.iter().map(|s| s.dirs).sum()
+    }
+
+    pub fn files(&self) -> usize {
+        self.roots.iter().map(|s| s.files).sum()
+    }
+
+    // node_modules directories the walker reported, nested ones included
+    pub fn node_modules_found(&self) -> usize {
+        self.roots.iter().map(|s| s.node_modules).sum()
+    }
+
+    pub fn ignored(&self) -> usize {
+        self.roots.iter().map(|s| s.ignored).sum()
+    }
+
+    pub fn entries_per_sec(&self) -> f64 {
+        let entries = (self.dirs() + self.files()) as f64;
+        if self.elapsed.as_secs_f64() > 0.0 {
+            entries / self.elapsed.as_secs_f64()
+        } else {
+            entries // Avoid division by zero
+        }
     }
-    result
 }
 
-// Main directory walker function
-pub fn walk_directories() {
-    let start = Instant::now();
+// Keeps only the node_modules directories that are not inside another one,
+// the walker can report nested ones when their parent is not pruned in time
+pub fn outermost_node_modules(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut outermost: Vec<PathBuf> = paths
+        .iter()
+        .filter(|path| is_outermost(path))
+        .cloned()
+        .collect();
+    outermost.sort();
+    outermost.dedup();
+    outermost
+}
+
+// True if no ancestor of the node_modules directory is a node_modules itself
+fn is_outermost(node_modules: &Path) -> bool {
+    node_modules
+        .parent()
+        .is_none_or(|parent| !parent.components().any(|c| c.as_os_str() == "node_modules"))
+}
 
-    let root_path = "/";
-
-    // Stats tracking with pre-allocated capacity
-    let file_count = Arc::new(AtomicUsize::new(0));
-    let dir_count = Arc::new(AtomicUsize::new(0));
-    let node_modules_count = Arc::new(AtomicUsize::new(0));
-    let ignored_count = Arc::new(AtomicUsize::new(0));
-
-    // Determine thread count
-    let num_threads = std::thread::available_parallelism()
-        .map(|p| p.get())
-        .unwrap_or(4);
-
-    info!(
-        "Using {:?} threads for traversal starting from {:?}",
-        num_threads, root_path
-    );
-    println!();
-    let spinner = start_spinner("Walking file tree...", get_ticks(SpinnerTheme::FileWalker));
-    // utils::g_utils::start_spinner(Some("Walking through your file system!".to_string()));
-    // Pre-allocate collections with appropriate initial capacity
-    let node_modules_locations = Arc::new(Mutex::new(Vec::with_capacity(2000)));
-    let node_modules_locations_clone = Arc::clone(&node_modules_locations);
-
-    // Use HashSet for faster path lookups
-    let skip_paths = Arc::new(Mutex::new(HashSet::<String>::default()));
-    let skip_paths_clone = Arc::clone(&skip_paths);
+// Marks what lies inside a node_modules, the flag of a directory is passed
+// down to every directory below it
+#[derive(Debug, Default)]
+struct WalkState;
+
+impl ClientState for WalkState {
+    type ReadDirState = bool;
+    type DirEntryState = EntryState;
+}
+
+#[derive(Debug, Default)]
+enum EntryState {
+    // Outside any node_modules, or only looking for them
+    #[default]
+    Unread,
+    Read(EntryFacts),
+    // A node_modules nothing changed in, served from the index unread
+    Indexed,
+}
+
+// Everything the walkers of every root share
+struct WalkContext<'a> {
+    excludes: &'a Arc<ExcludeSet>,
+    // None when the walk only looks for node_modules
+    rules: Option<&'a Arc<RuleMatcher>>,
+    index: Option<&'a Arc<ScanIndex>>,
+    follow_links: bool,
+    observer: &'a dyn ScanObserver,
+    progress: &'a WalkProgress,
+    match_stats: Mutex<MatchStats>,
+}
+
+// What the walker found below a single root
+#[derive(Default)]
+struct RootWalk {
+    node_modules: Vec<PathBuf>,
+    nested: Vec<PathBuf>,
+    matches: Vec<PatternMatch>,
+    errors: Vec<ScanError>,
+    reused: Vec<PathBuf>,
+    indexed: Vec<(PathBuf, IndexedNodeModules)>,
+    trees: Vec<(PathBuf, TreeStats)>,
+}
+
+// Reports a node_modules as sized and matched and keeps what it added up
+fn finish_tally(tally: NodeModulesTally, ctx: &WalkContext, found: &mut RootWalk) {
+    let path = tally.path.clone();
+    let (tree, matches, indexed) = tally.finish(&ctx.match_stats, ctx.observer);
+    found.trees.push((path.clone(), tree));
+    found.matches.extend(matches);
+    if let Some(indexed) = indexed {
+        found.indexed.push((path, indexed));
+    }
+}
+
+// Serves a node_modules from the index as if it was walked
+fn reuse_indexed(
+    path: &Path,
+    indexed: &IndexedNodeModules,
+    stats: &RootStats,
+    ctx: &WalkContext,
+    found: &mut RootWalk,
+) {
+    // The node_modules itself was already counted
+    let nested: Vec<PathBuf> = indexed
+        .dirs
+        .iter()
+        .filter(|(dir, _)| dir.file_name().is_some_and(|name| name == "node_modules"))
+        .map(|(dir, _)| path.join(dir))
+        .collect();
+    stats
+        .dirs
+        .fetch_add(indexed.dirs.len().saturating_sub(1), Ordering::Relaxed);
+    stats
+        .files
+        .fetch_add(indexed.files as usize, Ordering::Relaxed);
+    stats.node_modules.fetch_add(nested.len(), Ordering::Relaxed);
+    found.nested.extend(nested);
+
+    let tree = indexed.tree();
+    ctx.observer.on_size_computed(path, &tree);
+    found.trees.push((path.to_path_buf(), tree));
+    let matches = indexed.matches(path);
+    for m in &matches {
+        ctx.observer.on_match(m);
+    }
+    ctx.observer.on_node_modules_matched(path, &matches);
+    ctx.match_stats.lock().unwrap().record(&matches, Vec::new());
+    found.matches.extend(matches);
+    found.reused.push(path.to_path_buf());
+}
+
+fn walk_root(
+    root_path: &Path,
+    num_threads: usize,
+    stats: &Arc<RootStats>,
+    ctx: &WalkContext,
+) -> RootWalk {
+    // Excluded entries are dropped before jwalk reads them, so excluded
+    // directories are never descended into
+    let prune_excludes = Arc::clone(ctx.excludes);
+    let prune_stats = Arc::clone(stats);
+    let rules = ctx.rules.map(Arc::clone);
+    let index = ctx.index.map(Arc::clone);
+    let follow_links = ctx.follow_links;
 
     // Configure walker
-    let walker = WalkDirGeneric::<((), ())>::new(root_path)
+    let walker = WalkDirGeneric::<WalkState>::new(root_path)
         .skip_hidden(false)
-        .follow_links(false)
+        .follow_links(ctx.follow_links)
         .sort(false)
-        .parallelism(jwalk::Parallelism::RayonNewPool(num_threads));
-
-    // Clone counters
-    let file_count_clone = Arc::clone(&file_count);
-    let dir_count_clone = Arc::clone(&dir_count);
-    let node_modules_count_clone = Arc::clone(&node_modules_count);
-    let ignored_count_clone = Arc::clone(&ignored_count);
-
-    // Process walkrer
-    #[allow(clippy::unnecessary_filter_map)]
-    let processed = walker
-        .into_iter()
-        .filter_map(|entry_result| {
-            // Skip errors
-            entry_result.ok()
-        })
-        .filter_map(|entry| {
-            // Fast filter for ignored paths
-            if is_ignored(&entry) {
-                ignored_count_clone.fetch_add(1, Ordering::Relaxed);
-                return None;
+        .parallelism(jwalk::Parallelism::RayonNewPool(num_threads))
+        .process_read_dir(move |_depth, path, in_node_modules, children| {
+            if !prune_excludes.is_empty() {
+                children.retain(|entry_result| match entry_result {
+                    Ok(entry) if prune_excludes.is_excluded(&entry.path()) => {
+                        prune_stats.ignored.fetch_add(1, Ordering::Relaxed);
+                        false
+                    }
+                    _ => true,
+                });
             }
 
-            // Check if path should be skipped (inside node_modules)
-            let path = entry.path();
-            let path_str = path.to_string_lossy();
-
-            // Fast check of skip paths
-            let should_skip = {
-                let skip_set = skip_paths.lock().unwrap();
-                // Iterate through ancestors to find if any parent is in the skip set
-                let mut current = Some(path.as_path());
-                let mut should_skip = false;
-                while let Some(p) = current {
-                    if let Some(p_str) = p.to_str() {
-                        if skip_set.contains(p_str) && path_str != p_str {
-                            should_skip = true;
-                            break;
-                        }
+            // Everything in a node_modules is matched and sized right here on
+            // the walker threads, or not read at all when only looking for them
+            *in_node_modules |= path.file_name().is_some_and(|name| name == "node_modules");
+            for entry in children.iter_mut().flatten() {
+                let is_node_modules = entry.file_type.is_dir() && entry.file_name == "node_modules";
+                let unchanged = || {
+                    index
+                        .as_ref()
+                        .is_some_and(|index| index.fresh(&entry.path(), follow_links).is_some())
+                };
+                match &rules {
+                    None if is_node_modules => entry.read_children_path = None,
+                    Some(_) if is_node_modules && !*in_node_modules && unchanged() => {
+                        entry.read_children_path = None;
+                        entry.client_state = EntryState::Indexed;
                     }
-                    current = p.parent();
-                }
-                should_skip
-            };
-
-            if should_skip { None } else { Some(entry) }
-        })
-        .filter_map(|entry| {
-            if entry.file_type.is_dir() {
-                dir_count_clone.fetch_add(1, Ordering::Relaxed);
-
-                // Check if node_modules directory
-                if entry.file_name.to_string_lossy() == "node_modules" {
-                    node_modules_count_clone.fetch_add(1, Ordering::Relaxed);
-                    let path = entry.path().to_string_lossy().to_string();
-
-                    // Use thread-local storage to batch updates
-                    LOCAL_NODE_MODULES.with(|local_paths| {
-                        let mut paths = local_paths.borrow_mut();
-                        paths.push(path);
-
-                        // Only lock the global collections when we have enough items
-                        if paths.len() >= 20 {
-                            // Batch update skip paths
-                            {
-                                let mut skip_set = skip_paths_clone.lock().unwrap();
-                                for path in paths.iter() {
-                                    skip_set.insert(path.clone());
-                                }
-                            }
-
-                            // Batch update locations
-                            {
-                                let mut locations = node_modules_locations_clone.lock().unwrap();
-                                locations.extend(paths.drain(..));
-                            }
-                        }
-                    });
+                    Some(rules) if *in_node_modules || is_node_modules => {
+                        let metadata = entry.metadata().map_err(|e| ScanError::from_jwalk(&e));
+                        let facts =
+                            EntryFacts::read(&entry.path(), entry.file_type, metadata, rules);
+                        entry.client_state = EntryState::Read(facts);
+                    }
+                    _ => {}
                 }
-            } else {
-                file_count_clone.fetch_add(1, Ordering::Relaxed);
             }
-            Some(())
-        })
-        .count();
-
-    // Flush any remaining items in thread-local storage
-    LOCAL_NODE_MODULES.with(|local_paths| {
-        let paths = local_paths.borrow();
-        if !paths.is_empty() {
-            // Update skip paths
-            {
-                let mut skip_set = skip_paths_clone.lock().unwrap();
-                for path in paths.iter() {
-                    skip_set.insert(path.clone());
+        });
+
+    let mut found = RootWalk::default();
+    // The outermost node_modules being summed up, entries come depth first
+    let mut tally: Option<NodeModulesTally> = None;
+
+    for entry_result in walker {
+        // Unreadable entries are recorded and skipped
+        let mut entry = match entry_result {
+            Ok(entry) => entry,
+            Err(e) => {
+                stats.errors.fetch_add(1, Ordering::Relaxed);
+                // Errors come before the entry that would finish the tally,
+                // a sibling of the node_modules must not spoil it
+                if let Some(tally) = &mut tally
+                    && e.path().is_some_and(|path| path.starts_with(&tally.path))
+                {
+                    tally.mark_unreadable();
                 }
+                let e = ScanError::from_jwalk(&e);
+                ctx.observer.on_error(&e);
+                found.errors.push(e);
+                continue;
             }
+        };
 
-            // Update locations
-            {
-                let mut locations = node_modules_locations_clone.lock().unwrap();
-                locations.extend(paths.iter().cloned());
+        if tally.as_ref().is_some_and(|t| entry.depth <= t.depth) {
+            finish_tally(tally.take().unwrap(), ctx, &mut found);
+        }
+
+        let path = entry.path();
+        let is_dir = entry.file_type.is_dir();
+        if is_dir {
+            let dirs = stats.dirs.fetch_add(1, Ordering::Relaxed);
+            if dirs.is_multiple_of(CURRENT_PATH_EVERY) {
+                ctx.progress.visit(&path);
             }
+            ctx.observer.on_dir_entered(&path);
+
+            // Check if node_modules directory
+            if entry.file_name == "node_modules" {
+                stats.node_modules.fetch_add(1, Ordering::Relaxed);
+                if tally.is_none() && is_outermost(&path) {
+                    ctx.observer.on_node_modules_found(&path);
+                    found.node_modules.push(path.clone());
+                    let indexed = ctx.index.and_then(|index| index.node_modules.get(&path));
+                    match (&entry.client_state, indexed) {
+                        (EntryState::Indexed, Some(indexed)) => {
+                            reuse_indexed(&path, indexed, stats, ctx, &mut found);
+                        }
+                        _ if ctx.rules.is_some() => {
+                            let index = ctx.index.is_some();
+                            tally = Some(NodeModulesTally::new(path.clone(), entry.depth, index));
+                        }
+                        _ => {}
+                    }
+                } else {
+                    found.nested.push(path.clone());
+                }
+            }
+        } else {
+            stats.files.fetch_add(1, Ordering::Relaxed);
         }
-    });
 
-    let elapsed = start.elapsed();
-    stop_spinner(spinner, "Done walking");
-    println!();
-    // Print benchmark results
-    info!("Traversal completed in {:.2?}", elapsed);
-    info!("Directories scanned: {}", dir_count.load(Ordering::Relaxed));
-    info!("Files scanned: {}", file_count.load(Ordering::Relaxed));
-    info!(
-        "node_modules directories found: {}",
-        node_modules_count.load(Ordering::Relaxed)
-    );
-    info!("Paths ignored: {}", ignored_count.load(Ordering::Relaxed));
-    info!("Total entries processed: {}", processed);
-
-    // Calculate and print processing speed
-    let total_entries = dir_count.load(Ordering::Relaxed) + file_count.load(Ordering::Relaxed);
-    let speed = if elapsed.as_secs_f64() > 0.0 {
-        total_entries as f64 / elapsed.as_secs_f64()
-    } else {
-        total_entries as f64 // Avoid division by zero
-    };
-    info!("Processing speed: {:.2} entries/sec", speed);
-
-    // Calculate and print node_modules finding speed
-    let node_modules_count_print = node_modules_count.load(Ordering::Relaxed);
-    let node_modules_speed = if elapsed.as_secs_f64() > 0.0 {
-        node_modules_count_print as f64 / elapsed.as_secs_f64()
-    } else {
-        node_modules_count_print as f64 // Avoid division by zero
-    };
-    info!(
-        "node_modules finding speed: {:.2} node_modules/sec",
-        node_modules_speed
-    );
-
-    // Print a sample of found node_modules locations
-    info!("Sample of node_modules locations found:\n");
-    let locations = node_modules_locations.lock().unwrap();
-    let display_count = std::cmp::min(locations.len(), 10); // Display up to 10 locations
-
-    for i in 0..display_count {
-        info!("  - {}", locations[i]);
+        if let (Some(tally), EntryState::Read(facts)) =
+            (&mut tally, std::mem::take(&mut entry.client_state))
+        {
+            tally.add(path, is_dir, facts, &mut found.errors, ctx.observer);
+        }
     }
 
-    if node_modules_count_print > 10 {
-        info!("  ... and {} more \n", locations.len());
+    if let Some(done) = tally {
+        finish_tally(done, ctx, &mut found);
+    }
+    found
+}
+
+// Main directory walker function, scans every root in parallel and returns
+// the outermost node_modules directories found across all of them. With
+// `rules` every node_modules is also sized and matched in the same pass, and
+// with `index` the ones nothing changed in are served from it unread.
+// Progress goes to `observer`, from the walker threads, and the counters can
+// be read from `progress` while the walk runs
+pub fn walk_directories(
+    roots: &[PathBuf],
+    excludes: &Arc<ExcludeSet>,
+    rules: Option<&Arc<RuleMatcher>>,
+    index: Option<&Arc<ScanIndex>>,
+    options: &WalkOptions,
+    observer: &dyn ScanObserver,
+    progress: &WalkProgress,
+) -> WalkResult {
+    let start = Instant::now();
+
+    // A root that is excluded itself would come back empty, say why instead
+    let (roots, mut errors) = normalize_roots(roots);
+    for e in &errors {
+        observer.on_error(e);
+    }
+    if let Some(rules) = rules
+        && rules.is_empty()
+    {
+        let message = format!(
+            "No rulesets up to '{}' found in configuration",
+            rules.level()
+        );
+        error!("{}", message);
+        let e = ScanError::Config(message);
+        observer.on_error(&e);
+        errors.push(e);
+    }
+    let roots: Vec<PathBuf> = roots
+        .into_iter()
+        .filter(|root| match excludes.matching_rule(root) {
+            Some(rule) => {
+                warn!(
+                    "Skipping root {}: excluded by {} rule '{}' ({})",
+                    root.display(),
+                    rule.kind_name(),
+                    rule.raw,
+                    rule.source
+                );
+                false
+            }
+            None => true,
+        })
+        .collect();
+    if roots.is_empty() {
+        warn!("No valid directories to scan");
+        progress.finish();
+        let result = WalkResult {
+            errors,
+            ..WalkResult::default()
+        };
+        observer.on_walk_finished(&result, excludes);
+        return result;
     }
-    info!("Reading patterns!");
 
-    let locations_pathbuff = convert_string_to_pathbuf(&locations);
+    // Determine thread count, shared between the roots
+    let num_threads = options.threads();
+    let threads_per_root = std::cmp::max(1, num_threads / roots.len());
 
-    #[allow(unused_variables)]
-    let mut matched_paths: Vec<PathBuf> = Vec::new();
+    observer.on_walk_started(&roots, num_threads);
 
-    matched_paths = matcher::matching_pattern(&locations_pathbuff);
+    let ctx = WalkContext {
+        excludes,
+        rules,
+        index,
+        follow_links: options.follow_links,
+        observer,
+        progress,
+        match_stats: Mutex::new(MatchStats::default()),
+    };
+
+    // Walk every root on its own thread
+    let results: Vec<(RootSummary, RootWalk)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = roots
+            .iter()
+            .map(|root| {
+                let ctx = &ctx;
+                scope.spawn(move || {
+                    let root_start = Instant::now();
+                    let stats = Arc::new(RootStats::default());
+                    progress.track(&stats);
+                    let found = walk_root(root, threads_per_root, &stats, ctx);
+                    let summary = RootSummary {
+                        root: root.clone(),
+                        files: stats.files.load(Ordering::Relaxed),
+                        dirs: stats.dirs.load(Ordering::Relaxed),
+                        node_modules: stats.node_modules.load(Ordering::Relaxed),
+                        ignored: stats.ignored.load(Ordering::Relaxed),
+                        elapsed: root_start.elapsed(),
+                    };
+                    (summary, found)
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Walker thread panicked"))
+            .collect()
+    });
+
+    let elapsed = start.elapsed();
+    progress.finish();
+
+    let mut locations: Vec<PathBuf> = Vec::new();
+    let mut nested: Vec<PathBuf> = Vec::new();
+    let mut matches: Vec<PatternMatch> = Vec::new();
+    let mut reused: Vec<PathBuf> = Vec::new();
+    let mut indexed: Vec<(PathBuf, IndexedNodeModules)> = Vec::new();
+    let mut trees: Vec<(PathBuf, TreeStats)> = Vec::new();
+    let mut summaries: Vec<RootSummary> = Vec::with_capacity(results.len());
+    for (summary, found) in results {
+        locations.extend(found.node_modules);
+        nested.extend(found.nested);
+        matches.extend(found.matches);
+        errors.extend(found.errors);
+        reused.extend(found.reused);
+        indexed.extend(found.indexed);
+        trees.extend(found.trees);
+        summaries.push(summary);
+    }
+    // The walker threads finish directories in any order
+    matches.sort_by(|a, b| a.path.cmp(&b.path));
+    nested.sort();
+
+    let result = WalkResult {
+        node_modules: outermost_node_modules(&locations),
+        nested,
+        matches,
+        reused,
+        indexed,
+        trees,
+        roots: summaries,
+        elapsed,
+        errors,
+    };
+    observer.on_walk_finished(&result, excludes);
+    if let Some(rules) = rules {
+        ctx.match_stats.lock().unwrap().log_summary(rules.level());
+        observer.on_matching_finished(&result.matches);
+    }
+    result
 }
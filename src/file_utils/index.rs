@@ -0,0 +1,532 @@
+use chrono::{DateTime, Local, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::config::Tier;
+use crate::config::excludes::ExcludeSet;
+use crate::file_utils::fs_utils::write_atomic;
+use crate::file_utils::matcher::PatternMatch;
+use crate::file_utils::rules::RuleMatcher;
+use crate::utils::age::format_age;
+use crate::utils::read_size::{TreeStats, bytes_to_mb};
+
+// Bumped whenever the index layout changes, older indexes are rebuilt
+pub const INDEX_VERSION: u32 = 2;
+
+/// `$XDG_CACHE_HOME/node-cleaner/index.json`, or `~/.cache/node-cleaner/index.json`
+pub fn default_index_path() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("node-cleaner")
+        .join("index.json")
+}
+
+/// Changes whenever a scan could match differently: other rules, excludes or
+/// symlink handling. Indexed matches are only reused under the same value.
+pub fn fingerprint(rules: &RuleMatcher, excludes: &ExcludeSet, follow_links: bool) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    INDEX_VERSION.hash(&mut hasher);
+    rules.hash(&mut hasher);
+    for rule in excludes.rules() {
+        rule.raw.hash(&mut hasher);
+    }
+    follow_links.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Modification time in nanoseconds since the epoch
+fn mtime_ns(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_nanos() as u64)
+}
+
+/// A matched file, relative to its node_modules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedMatch {
+    pub path: PathBuf,
+    pub tier: Tier,
+    pub pattern: String,
+    pub size: u64,
+    // Checked with the size before the match is reused. Missing in version
+    // 1 indexes, which are rebuilt anyway.
+    #[serde(default)]
+    pub modified_ns: u64,
+}
+
+impl IndexedMatch {
+    // True if the file still has the size and mtime it was matched with
+    fn is_fresh(&self, node_modules: &Path, follow_links: bool) -> bool {
+        let path = node_modules.join(&self.path);
+        let metadata = if follow_links {
+            fs::metadata(&path)
+        } else {
+            fs::symlink_metadata(&path)
+        };
+        metadata.ok().is_some_and(|metadata| {
+            metadata.is_file()
+                && metadata.len() == self.size
+                && metadata.modified().ok().and_then(mtime_ns) == Some(self.modified_ns)
+        })
+    }
+}
+
+/// One node_modules as a scan left it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedNodeModules {
+    // Every directory in it, itself as an empty path, with its mtime in
+    // nanoseconds since the epoch. Adding, removing or renaming an entry
+    // changes the mtime of the directory holding it.
+    pub dirs: Vec<(PathBuf, u64)>,
+    pub bytes: u64,
+    pub files: u64,
+    pub modified_ns: Option<u64>,
+    pub matches: Vec<IndexedMatch>,
+}
+
+impl IndexedNodeModules {
+    /// None if a directory or a matched file has no usable mtime, it could
+    /// never be checked. `match_modified` holds the mtime of every match.
+    pub fn new(
+        node_modules: &Path,
+        dirs: Vec<(PathBuf, Option<SystemTime>)>,
+        tree: &TreeStats,
+        matches: &[PatternMatch],
+        match_modified: &[Option<SystemTime>],
+    ) -> Option<Self> {
+        let dirs = dirs
+            .into_iter()
+            .map(|(dir, modified)| Some((dir, mtime_ns(modified?)?)))
+            .collect::<Option<Vec<_>>>()?;
+        let matches = matches
+            .iter()
+            .zip(match_modified)
+            .map(|(m, modified)| {
+                Some(IndexedMatch {
+                    path: m
+                        .path
+                        .strip_prefix(node_modules)
+                        .unwrap_or(&m.path)
+                        .to_path_buf(),
+                    tier: m.tier,
+                    pattern: m.pattern.clone(),
+                    size: m.size,
+                    modified_ns: mtime_ns((*modified)?)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            dirs,
+            bytes: tree.bytes,
+            files: tree.files,
+            modified_ns: tree.modified.and_then(mtime_ns),
+            matches,
+        })
+    }
+
+    /// True if no directory in it and no matched file changed since it was
+    /// indexed, one `stat` per directory and match instead of reading every
+    /// entry. Other files edited in place keep their directory's mtime, their
+    /// new size is only seen after something else in the directory changes.
+    pub fn is_fresh(&self, node_modules: &Path, follow_links: bool) -> bool {
+        let dirs_fresh = self.dirs.iter().all(|(dir, indexed)| {
+            let path = node_modules.join(dir);
+            let metadata = if follow_links {
+                fs::metadata(&path)
+            } else {
+                fs::symlink_metadata(&path)
+            };
+            metadata
+                .ok()
+                .filter(|metadata| metadata.is_dir())
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(mtime_ns)
+                == Some(*indexed)
+        });
+        dirs_fresh
+            && self
+                .matches
+                .iter()
+                .all(|m| m.is_fresh(node_modules, follow_links))
+    }
+
+    pub fn tree(&self) -> TreeStats {
+        TreeStats {
+            bytes: self.bytes,
+            files: self.files,
+            modified: self
+                .modified_ns
+                .map(|ns| UNIX_EPOCH + Duration::from_nanos(ns)),
+        }
+    }
+
+    pub fn matches(&self, node_modules: &Path) -> Vec<PatternMatch> {
+        self.matches
+            .iter()
+            .map(|m| PatternMatch {
+                path: node_modules.join(&m.path),
+                tier: m.tier,
+                pattern: m.pattern.clone(),
+                size: m.size,
+            })
+            .collect()
+    }
+
+    fn reclaimable(&self) -> u64 {
+        self.matches.iter().map(|m| m.size).sum()
+    }
+}
+
+/// The node_modules of the last scans, so unchanged ones are not read again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanIndex {
+    pub version: u32,
+    pub updated: String,
+    // See `fingerprint`
+    pub fingerprint: u64,
+    // How the last scan went: node_modules served from the index, and read
+    pub reused: usize,
+    pub rescanned: usize,
+    pub node_modules: BTreeMap<PathBuf, IndexedNodeModules>,
+}
+
+#[derive(Debug)]
+pub enum IndexError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    Version {
+        path: PathBuf,
+        found: u32,
+    },
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::Io { path, source } => {
+                write!(f, "Failed to access index {}: {}", path.display(), source)
+            }
+            IndexError::Parse { path, source } => {
+                write!(f, "Failed to parse index {}: {}", path.display(), source)
+            }
+            IndexError::Version { path, found } => write!(
+                f,
+                "Index {} has version {}, this build only reads version {}",
+                path.display(),
+                found,
+                INDEX_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+impl ScanIndex {
+    pub fn read(path: &Path) -> Result<Self, IndexError> {
+        let data = fs::read(path).map_err(|source| IndexError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let index: ScanIndex =
+            serde_json::from_slice(&data).map_err(|source| IndexError::Parse {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        if index.version != INDEX_VERSION {
+            return Err(IndexError::Version {
+                path: path.to_path_buf(),
+                found: index.version,
+            });
+        }
+        Ok(index)
+    }
+
+    /// The index at `path` if it was built with the same `fingerprint`,
+    /// otherwise an empty one. A broken index is only worth a warning, the
+    /// scan just reads everything.
+    pub fn load(path: &Path, fingerprint: u64) -> Self {
+        match Self::read(path) {
+            Ok(index) if index.fingerprint == fingerprint => index,
+            Ok(_) => {
+                debug!(
+                    "Rules or excludes changed since {} was written, rebuilding it",
+                    path.display()
+                );
+                Self::default()
+            }
+            Err(IndexError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+                Self::default()
+            }
+            Err(e @ IndexError::Version { .. }) => {
+                debug!("{}, rebuilding it", e);
+                Self::default()
+            }
+            Err(e) => {
+                warn!("Ignoring scan index: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Written next to its final place first, so an interrupted run never
+    /// leaves half an index behind and runs at the same time never mix theirs
+    pub fn write(&self, path: &Path) -> Result<(), IndexError> {
+        let io_error = |source| IndexError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        let data = serde_json::to_vec(self).map_err(|source| IndexError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        write_atomic(path, &data).map_err(io_error)
+    }
+
+    /// The indexed node_modules at `path`, if nothing in it changed since
+    pub fn fresh(&self, path: &Path, follow_links: bool) -> Option<&IndexedNodeModules> {
+        self.node_modules
+            .get(path)
+            .filter(|indexed| indexed.is_fresh(path, follow_links))
+    }
+
+    /// Replaces everything below `roots` with what a scan of them found:
+    /// the `reused` entries stay, `rescanned` ones are added and the rest is
+    /// gone. Entries of other roots are kept for their next scan.
+    pub fn update(
+        &mut self,
+        fingerprint: u64,
+        roots: &[PathBuf],
+        reused: &[PathBuf],
+        rescanned: Vec<(PathBuf, IndexedNodeModules)>,
+    ) {
+        let reused_set: HashSet<&PathBuf> = reused.iter().collect();
+        self.node_modules.retain(|path, _| {
+            reused_set.contains(path) || !roots.iter().any(|root| path.starts_with(root))
+        });
+        self.rescanned = rescanned.len();
+        self.node_modules.extend(rescanned);
+        self.reused = reused.len();
+        self.version = INDEX_VERSION;
+        self.updated = Utc::now().to_rfc3339();
+        self.fingerprint = fingerprint;
+    }
+}
+
+/// Writes what the index at `path` holds to `out`, used by `index stats`
+pub fn print_stats(
+    out: &mut impl Write,
+    path: &Path,
+    index: &ScanIndex,
+    fingerprint: u64,
+) -> io::Result<()> {
+    let (dirs, files, bytes, matches, reclaimable) = index.node_modules.values().fold(
+        (0, 0, 0, 0, 0),
+        |(dirs, files, bytes, matches, reclaimable), indexed| {
+            (
+                dirs + indexed.dirs.len(),
+                files + indexed.files,
+                bytes + indexed.bytes,
+                matches + indexed.matches.len(),
+                reclaimable + indexed.reclaimable(),
+            )
+        },
+    );
+    let on_disk = fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let age = DateTime::parse_from_rfc3339(&index.updated)
+        .ok()
+        .and_then(|updated| {
+            SystemTime::now()
+                .duration_since(updated.with_timezone(&Utc).into())
+                .ok()
+        });
+
+    writeln!(
+        out,
+        "Index {} ({:.2} MB)",
+        path.display(),
+        bytes_to_mb(on_disk)
+    )?;
+    match (DateTime::parse_from_rfc3339(&index.updated), age) {
+        (Ok(updated), Some(age)) => writeln!(
+            out,
+            "  updated {} ({} ago)",
+            updated.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            format_age(age)
+        )?,
+        _ => writeln!(out, "  updated {}", index.updated)?,
+    }
+    writeln!(
+        out,
+        "  {} node_modules, {} directories, {} files, {:.2} MB",
+        index.node_modules.len(),
+        dirs,
+        files,
+        bytes_to_mb(bytes)
+    )?;
+    writeln!(
+        out,
+        "  {} matched files, {:.2} MB reclaimable",
+        matches,
+        bytes_to_mb(reclaimable)
+    )?;
+    writeln!(
+        out,
+        "  last scan reused {} and read {} node_modules",
+        index.reused, index.rescanned
+    )?;
+    if index.fingerprint != fingerprint {
+        writeln!(
+            out,
+            "  built with other rules, excludes or symlink handling, the next scan rebuilds it"
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config::{ConfigSource, EMBEDDED_PATTERNS, parse_config};
+
+    // Far enough from now that no write during the test lands on it
+    fn past(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_000_000_000 + secs)
+    }
+
+    fn set_mtime(path: &Path, time: SystemTime) {
+        fs::File::open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    // A node_modules with one matched file, indexed as it is on disk
+    fn indexed(root: &Path) -> (PathBuf, IndexedNodeModules) {
+        let node_modules = root.join("node_modules");
+        let file = node_modules.join("pkg/README.md");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "readme").unwrap();
+        set_mtime(&file, past(1));
+        set_mtime(&node_modules.join("pkg"), past(2));
+        set_mtime(&node_modules, past(3));
+
+        let m = PatternMatch {
+            path: file,
+            tier: Tier::Safe,
+            pattern: "README.md".to_string(),
+            size: 6,
+        };
+        let tree = TreeStats {
+            bytes: 6,
+            files: 1,
+            modified: Some(past(1)),
+        };
+        let dirs = vec![
+            (PathBuf::new(), Some(past(3))),
+            (PathBuf::from("pkg"), Some(past(2))),
+        ];
+        let entry =
+            IndexedNodeModules::new(&node_modules, dirs, &tree, &[m], &[Some(past(1))]).unwrap();
+        assert!(entry.is_fresh(&node_modules, false));
+        (node_modules, entry)
+    }
+
+    fn index_with(fingerprint: u64, node_modules: PathBuf, entry: IndexedNodeModules) -> ScanIndex {
+        let mut index = ScanIndex::default();
+        let root = node_modules.parent().unwrap().to_path_buf();
+        index.update(fingerprint, &[root], &[], vec![(node_modules, entry)]);
+        index
+    }
+
+    #[test]
+    fn changed_directory_mtime_invalidates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (node_modules, entry) = indexed(tmp.path());
+
+        fs::write(node_modules.join("pkg/index.js"), "").unwrap();
+        set_mtime(&node_modules.join("pkg"), past(20));
+        assert!(!entry.is_fresh(&node_modules, false));
+    }
+
+    #[test]
+    fn changed_matched_file_invalidates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (node_modules, entry) = indexed(tmp.path());
+        let file = node_modules.join("pkg/README.md");
+
+        // Edited in place, the directories keep their mtimes
+        fs::write(&file, "a longer readme").unwrap();
+        set_mtime(&file, past(1));
+        assert!(!entry.is_fresh(&node_modules, false));
+
+        fs::write(&file, "README").unwrap();
+        set_mtime(&file, past(1));
+        assert!(entry.is_fresh(&node_modules, false));
+        set_mtime(&file, past(10));
+        assert!(!entry.is_fresh(&node_modules, false));
+    }
+
+    #[test]
+    fn other_fingerprint_discards_the_index() {
+        let config = parse_config(EMBEDDED_PATTERNS, &ConfigSource::Embedded).unwrap();
+        let safe = RuleMatcher::new(&config, Tier::Safe).unwrap();
+        let caution = RuleMatcher::new(&config, Tier::Caution).unwrap();
+        let excludes = ExcludeSet::new(Vec::new());
+        let current = fingerprint(&safe, &excludes, false);
+        assert_ne!(current, fingerprint(&caution, &excludes, false));
+        assert_ne!(current, fingerprint(&safe, &excludes, true));
+
+        let tmp = tempfile::tempdir().unwrap();
+        let (node_modules, entry) = indexed(tmp.path());
+        let path = tmp.path().join("index.json");
+        index_with(current, node_modules.clone(), entry)
+            .write(&path)
+            .unwrap();
+
+        let index = ScanIndex::load(&path, current);
+        assert!(index.fresh(&node_modules, false).is_some());
+        let other = fingerprint(&caution, &excludes, false);
+        assert!(ScanIndex::load(&path, other).node_modules.is_empty());
+    }
+
+    #[test]
+    fn torn_or_foreign_index_loads_empty() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (node_modules, entry) = indexed(tmp.path());
+        let path = tmp.path().join("index.json");
+        let mut index = index_with(7, node_modules, entry);
+        index.write(&path).unwrap();
+        assert_eq!(ScanIndex::load(&path, 7).node_modules.len(), 1);
+
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() / 2]).unwrap();
+        assert!(ScanIndex::load(&path, 7).node_modules.is_empty());
+
+        index.version = INDEX_VERSION + 1;
+        fs::write(&path, serde_json::to_vec(&index).unwrap()).unwrap();
+        assert!(matches!(
+            ScanIndex::read(&path),
+            Err(IndexError::Version { .. })
+        ));
+        assert!(ScanIndex::load(&path, 7).node_modules.is_empty());
+    }
+}
@@ -1,279 +1,271 @@
+use std::fs::{FileType, Metadata};
 use std::path::{Path, PathBuf};
 use std::collections::HashMap;
-use once_cell::sync::Lazy;
 use std::sync::Mutex;
+use std::time::SystemTime;
 
-use log::{info, debug, error, trace};
-use walkdir::WalkDir;
+use log::{info, debug, trace, log_enabled, Level};
 
 
-use crate::config;
-use crate::config::cli::LOG_LEVEL;
-use crate::utils::g_utils::{iter_pattern_hits, start_spinner, stop_spinner, get_ticks, SpinnerTheme};
+use crate::config::config::Tier;
+use crate::error::ScanError;
+use crate::file_utils::index::IndexedNodeModules;
+use crate::file_utils::rules::{RuleMatcher, rule_relative_path};
+use crate::observer::ScanObserver;
+use crate::utils::g_utils::iter_pattern_hits;
+use crate::utils::read_size::TreeStats;
 
+// A path selected for removal, tagged with the tier and pattern that selected it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternMatch {
+    pub path: PathBuf,
+    pub tier: Tier,
+    pub pattern: String,
+    pub size: u64,
+}
+
+// The project owning a path: the directory holding its outermost node_modules
+pub fn project_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .filter(|ancestor| ancestor.file_name().is_some_and(|name| name == "node_modules"))
+        .last()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+}
 
-//Global Vec's to store DIR and FILE paths seperately 
-pub static FILES: Lazy<Mutex<Vec<PathBuf>>> = Lazy::new(|| Mutex::new(Vec::new()));
-pub static DIRS: Lazy<Mutex<Vec<PathBuf>>> = Lazy::new(|| Mutex::new(Vec::new()));
+// A package inside node_modules, `name` is `lodash` or `@babel/core`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwningPackage {
+    pub name: String,
+    pub root: PathBuf,
+}
 
+// Every package a path lives in, outermost first. For
+// `node_modules/a/node_modules/@s/b/x.js` that is `a` and `@s/b`.
+pub fn owning_packages(path: &Path) -> Vec<OwningPackage> {
+    let mut packages = Vec::new();
+    let mut prefix = PathBuf::new();
+    let mut components = path.components().peekable();
 
+    while let Some(component) = components.next() {
+        prefix.push(component);
+        if component.as_os_str() != "node_modules" {
+            continue;
+        }
 
+        let Some(first) = components.next() else { break };
+        let first_name = first.as_os_str().to_string_lossy().to_string();
+        prefix.push(first);
 
-// Main function to match patterns against node_modules directories
-pub fn matching_pattern(paths: &Vec<PathBuf>) -> Vec<PathBuf>  {
-    info!("Matching patterns for {:?} node_modules directories", paths.len());
-    println!();
-    let spinner = start_spinner("Matching patterns...", get_ticks(SpinnerTheme::PatternMatch));
+        // Entries like `.bin` or `.package-lock.json` are not packages
+        if first_name.starts_with('.') {
+            continue;
+        }
 
-    #[allow(unused_variables)]
-    let mut results: i32 = 0;
-    let mut safe_paths_array: Vec<PathBuf> = Vec::with_capacity(paths.len() * 10); // Pre-allocate more space
-    let mut pattern_hits: HashMap<String, i32> = HashMap::new();
-    
-    // Debug sampling settings
-    let max_debug_samples = 10; // Maximum number of debug samples to show per pattern
-    let mut debug_sample_counts: HashMap<String, i32> = HashMap::new();
-    
-    match config::config::read_patterns() {
-        Ok(config) => {
-            // info!("Successfully loaded patterns config");            
-            
-            // Get the safe ruleset once outside the loop
-            if let Some(safe_ruleset) = config.rules.get("safe") {
-                // For each node_modules directory
-                for node_modules_path in paths {
-                    trace!("Walking through directory: {}", node_modules_path.display());
-                    
-                    // Actually walk through the directory and check each file
-                    for entry_result in WalkDir::new(node_modules_path)
-                        .into_iter()
-                        .filter_map(Result::ok) {
-                        
-                        let entry_path = entry_result.path();
-                        
-                        // Only process files (not directories)
-                        if entry_result.file_type().is_file() {
-                            let path_str = entry_path.to_str().unwrap_or("");
-                            let file_name = entry_path.file_name()
-                                .and_then(|n| n.to_str())
-                                .unwrap_or("");
-                            
-                            // Check against each pattern
-                            for pattern in &safe_ruleset.patterns {
-                                // Try to match against full path and file name
-                                let matches = match_path_with_pattern(path_str, pattern) || 
-                                              match_path_with_pattern(file_name, pattern);
-                                
-                                if matches {
-                                    // Sample debug logging
-                                    let sample_count = debug_sample_counts.entry(pattern.clone()).or_insert(0);
-                                    *sample_count += 1;
-                                    
-                                    if *sample_count <= max_debug_samples {
-                                        debug!("Match #{} for pattern '{}': {}", 
-                                              *sample_count, pattern, entry_path.display());
-                                    } else if *sample_count == max_debug_samples + 1 {
-                                        debug!("Suppressing further debug output for pattern '{}'", pattern);
-                                    }
-                                    
-                                    safe_paths_array.push(entry_path.to_path_buf());
-                                    *pattern_hits.entry(pattern.clone()).or_insert(0) += 1;
-                                    results += 1;
-                                    break; // Move to next file after first match
-                                }
-                            }
-                        }
-                    }
+        let name = if first_name.starts_with('@') {
+            match components.next() {
+                Some(second) => {
+                    prefix.push(second);
+                    format!("{}/{}", first_name, second.as_os_str().to_string_lossy())
                 }
-            } else {
-                error!("No 'safe' ruleset found in configuration");
+                None => break,
             }
-        },
-        Err(e) => {
-            error!("Error loading patterns: {}", e);
+        } else {
+            first_name
+        };
+
+        // A file sitting directly in node_modules is not inside a package
+        if components.peek().is_none() {
+            break;
         }
+
+        packages.push(OwningPackage {
+            name,
+            root: prefix.clone(),
+        });
     }
-    stop_spinner(spinner, "Done matching patterns");
-    println!();
-    debug!("safe_paths_array Contains: {} items", safe_paths_array.len());
-    // info!("Found {} files which match the `safe` pattern", results);
-    debug!("Pattern hit summary:");
-    for (pattern, count) in &pattern_hits {
-        debug!("  - '{}': {} matches", pattern, count);
-    }
-    
-    if *LOG_LEVEL.lock().unwrap() == "DEBUG" {
-        iter_pattern_hits(&pattern_hits);
-    }
-    safe_paths_array
+
+    packages
 }
 
-// Enhanced pattern matching function with support for wildcards and case-insensitivity
-pub fn match_path_with_pattern(path_str: &str, pattern: &str) -> bool {
-    let mut matches = Vec::new();  
-    let mut match_result = false;
-    let mut match_reason = String::new();
-    
-    // Case 1: If pattern starts and ends with '/', it's meant to match a directory
-    if pattern.starts_with('/') && pattern.ends_with('/') {
-        let dir_name = &pattern[1..pattern.len()-1]; // Remove slashes
-        
-        // Convert to platform-specific path separators
-        #[cfg(not(windows))]
-        let normalized_path = path_str;
-        
-        #[cfg(windows)]
-        let normalized_path = path_str.replace('\\', "/");
-        
-        // Check if `/dir_name/` exists in the path as a complete directory segment
-        let dir_pattern = format!("/{}/", dir_name);
-        match_result = normalized_path.contains(&dir_pattern);
-        
-        if match_result {
-            match_reason = format!("Directory pattern '{}' found in path", dir_pattern);
-            matches.push(path_str); // Add to matches vector here
+
+// What a walker thread learned about an entry inside a node_modules while
+// listing its directory, so matching and sizing need no second walk
+#[derive(Debug, Default)]
+pub struct EntryFacts {
+    len: u64,
+    modified: Option<SystemTime>,
+    // The rule selecting the file, and the ignored package protecting it
+    rule: Option<(Tier, String)>,
+    protected_by: Option<(String, Tier)>,
+    // Unreadable metadata, the entry is neither sized nor removed
+    error: Option<ScanError>,
+}
+
+impl EntryFacts {
+    // Only files are matched, every tier up to the matcher's level is applied
+    // and the safest matching tier wins
+    pub fn read(path: &Path, file_type: FileType, metadata: Result<Metadata, ScanError>, rules: &RuleMatcher) -> Self {
+        let mut facts = EntryFacts::default();
+        if file_type.is_file() && let Some(rule) = rules.find(&rule_relative_path(path)) {
+            // Never touch anything inside an ignored package
+            facts.protected_by = rules.protected_by(path);
+            facts.rule = Some((rule.tier, rule.pattern.clone()));
         }
-    }
-    
-    // Case 2: Handle wildcard patterns like "readme*"
-    else if pattern.contains('*') {
-        let path_lower = path_str.to_lowercase();
-        let parts: Vec<&str> = pattern.split('*').collect();
-        
-        // Simple wildcard matching
-        if parts.len() == 2 {
-            let prefix = parts[0].to_lowercase();
-            let suffix = parts[1].to_lowercase();
-            
-            // Get the file name for boundary checking
-            let file_name = Path::new(path_str)
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("")
-                .to_lowercase();
-            
-            if suffix.is_empty() {
-                // Pattern like "readme*" - check if it's at the beginning of file name
-                // or is a complete path segment
-                let is_segment = is_exact_path_segment(&path_lower, &prefix);
-                let starts_with = file_name.starts_with(&prefix);
-                
-                match_result = is_segment || starts_with;
-                
-                if match_result {
-                    if is_segment {
-                        match_reason = format!("Prefix '{}' is an exact path segment", prefix);
-                    } else {
-                        match_reason = format!("Filename '{}' starts with '{}'", file_name, prefix);
-                    }
-                    matches.push(path_str); // Add to matches vector here
-                }
-            } else {
-                // Pattern like "read*me" - ensure both parts and check boundaries
-                let contains_prefix = path_lower.contains(&prefix);
-                let contains_suffix = path_lower.contains(&suffix);
-                let prefix_before_suffix = path_lower.find(&prefix).unwrap_or(usize::MAX) < 
-                                         path_lower.find(&suffix).unwrap_or(usize::MAX);
-                let combined = prefix.to_owned() + &suffix;
-                let is_segment = is_exact_path_segment(&path_lower, &combined);
-                let file_pattern_match = file_name.starts_with(&prefix) && file_name.ends_with(&suffix);
-                
-                match_result = contains_prefix && 
-                               contains_suffix && 
-                               prefix_before_suffix &&
-                               (is_segment || file_pattern_match);
-                
-                if match_result {
-                    if is_segment {
-                        match_reason = format!("Combined pattern '{}{}{}' is an exact path segment", 
-                                             prefix, "*", suffix);
-                    } else {
-                        match_reason = format!("Filename '{}' matches wildcard pattern '{}*{}'", 
-                                             file_name, prefix, suffix);
-                    }
-                    matches.push(path_str); // Add to matches vector here
+        match metadata {
+            Ok(metadata) => {
+                facts.modified = metadata.modified().ok();
+                if !file_type.is_dir() {
+                    facts.len = metadata.len();
                 }
             }
+            Err(e) => facts.error = Some(e),
         }
+        facts
     }
-    
-    // Case 3: Check for exact matches
-    else {
-        let path = Path::new(path_str); 
-        // Check if pattern matches the file name exactly
-        if let Some(file_name) = path.file_name() {
-            if let Some(file_str) = file_name.to_str() {
-                if file_str.to_lowercase() == pattern.to_lowercase() {
-                    match_result = true;
-                    match_reason = format!("Exact filename match: '{}'", file_str);
-                    matches.push(path_str); // Add to matches vector here
-                }
-            }
+}
+
+// A node_modules the walker is inside of, summed up entry by entry. The walker
+// yields a directory's whole subtree before moving on, so it is complete as
+// soon as an entry at its depth or above comes along.
+#[derive(Debug)]
+pub struct NodeModulesTally {
+    pub path: PathBuf,
+    pub depth: usize,
+    tree: TreeStats,
+    matches: Vec<PatternMatch>,
+    // The mtime of every match, for the index
+    match_modified: Vec<Option<SystemTime>>,
+    // Ignored packages that kept a matching file, once per file
+    protected: Vec<(String, Tier)>,
+    // Every directory with its mtime, relative to `path`, when it is indexed
+    dirs: Option<Vec<(PathBuf, Option<SystemTime>)>>,
+    // An entry could not be read, the next scan has to try again
+    unreadable: bool,
+}
+
+impl NodeModulesTally {
+    pub fn new(path: PathBuf, depth: usize, indexed: bool) -> Self {
+        Self {
+            path, depth,
+            tree: TreeStats::default(), matches: Vec::new(), match_modified: Vec::new(), protected: Vec::new(),
+            dirs: indexed.then(Vec::new),
+            unreadable: false,
         }
-        
-        // Check if pattern matches a file extension exactly
-        if !match_result {
-            if let Some(extension) = path.extension() {
-                if let Some(ext_str) = extension.to_str() {
-                    if ext_str.to_lowercase() == pattern.to_lowercase() {
-                        match_result = true;
-                        match_reason = format!("Exact extension match: '.{}'", ext_str);
-                        matches.push(path_str); // Add to matches vector here
-                    }
-                }
-            }
+    }
+
+    pub fn mark_unreadable(&mut self) {
+        self.unreadable = true;
+    }
+
+    // Symlinks (such as `node_modules/.bin`) count as their own size, like `get_tree_size`
+    pub fn add(&mut self, path: PathBuf, is_dir: bool, facts: EntryFacts, errors: &mut Vec<ScanError>, observer: &dyn ScanObserver) {
+        self.tree.modified = self.tree.modified.max(facts.modified);
+        if facts.error.is_some() {
+            self.unreadable = true;
+        } else if !is_dir {
+            self.tree.bytes += facts.len;
+            self.tree.files += 1;
         }
-        
-        // Check if pattern is an exact path segment (directory name)
-        if !match_result {
-            match_result = is_exact_path_segment(&path_str.to_lowercase(), &pattern.to_lowercase());
-            if match_result {
-                match_reason = format!("Exact path segment match: '{}'", pattern);
-                matches.push(path_str); // Add to matches vector here
-            }
+        if is_dir && let Some(dirs) = &mut self.dirs {
+            let relative = path.strip_prefix(&self.path).unwrap_or(&path).to_path_buf();
+            dirs.push((relative, facts.modified));
         }
+
+        let Some((tier, pattern)) = facts.rule else { return };
+        if let Some((name, ignore_tier)) = facts.protected_by {
+            trace!("Protected {} (package '{}' is ignored)", path.display(), name);
+            self.protected.push((name, ignore_tier));
+            return;
+        }
+        // A file that cannot be sized is not removed blindly
+        if let Some(e) = facts.error {
+            record(errors, observer, e);
+            return;
+        }
+        let m = PatternMatch { path, tier, pattern, size: facts.len };
+        observer.on_match(&m);
+        self.matches.push(m);
+        self.match_modified.push(facts.modified);
     }
-    
-    // Only log if there's a match
-    if match_result {
-        debug!("MATCH: '{}' with pattern '{}' - {}", path_str, pattern, match_reason);
+
+    // Reports the node_modules as sized and matched and hands back its
+    // totals and matches, with what to index unless something could not be read
+    pub fn finish(self, stats: &Mutex<MatchStats>, observer: &dyn ScanObserver) -> (TreeStats, Vec<PatternMatch>, Option<IndexedNodeModules>) {
+        observer.on_size_computed(&self.path, &self.tree);
+        observer.on_node_modules_matched(&self.path, &self.matches);
+        stats.lock().unwrap().record(&self.matches, self.protected);
+        let indexed = match self.dirs {
+            Some(dirs) if !self.unreadable => IndexedNodeModules::new(&self.path, dirs, &self.tree, &self.matches, &self.match_modified),
+            _ => None,
+        };
+        (self.tree, self.matches, indexed)
     }
-    
-    // Always call split_by_type with our matches
-    split_by_type(matches);
-    match_result
 }
 
-// Helper function to check if a string is an exact path segment
-pub fn is_exact_path_segment(path: &str, segment: &str) -> bool {
-    // Normalize path separators for consistent processing
-    #[cfg(windows)]
-    let normalized_path = path.replace('\\', "/");
-    
-    #[cfg(not(windows))]
-    let normalized_path = path;
-    
-    // Split the path into segments and check if any segment matches exactly
-    let path_segments: Vec<&str> = normalized_path.split('/').collect();
-    
-    path_segments.iter().any(|&s| s.to_lowercase() == segment.to_lowercase())
+// What the rules matched over a whole scan, logged once it is done
+#[derive(Debug, Default)]
+pub struct MatchStats {
+    matches: usize,
+    pattern_hits: HashMap<String, i32>,
+    tier_hits: HashMap<Tier, i32>,
+    // Protected package name -> (tier whose ignore list protects it, files kept)
+    protected: HashMap<String, (Tier, i32)>,
+    debug_sample_counts: HashMap<String, i32>,
 }
 
+// Maximum number of debug samples to show per pattern
+const MAX_DEBUG_SAMPLES: i32 = 10;
+
+impl MatchStats {
+    pub fn record(&mut self, matches: &[PatternMatch], protected: Vec<(String, Tier)>) {
+        self.matches += matches.len();
+        for m in matches {
+            // Sample debug logging
+            let sample_count = self.debug_sample_counts.entry(m.pattern.clone()).or_insert(0);
+            *sample_count += 1;
 
-// Helper function to split files and directories into global array's 
-// Arrays in turn will be used to remove files accordingly 
-fn split_by_type(path_str: Vec<&str>){
-    let mut local_files = Vec::new();
-    let mut local_dirs = Vec::new();
+            if *sample_count <= MAX_DEBUG_SAMPLES {
+                debug!("Match #{} for {} pattern '{}': {}",
+                      *sample_count, m.tier, m.pattern, m.path.display());
+            } else if *sample_count == MAX_DEBUG_SAMPLES + 1 {
+                debug!("Suppressing further debug output for pattern '{}'", m.pattern);
+            }
 
-    for s in path_str{
-        let path = Path::new(s);
-        if path.is_file(){
-            local_files.push(path.to_path_buf());
-        } else if path.is_dir(){
-            local_dirs.push(path.to_path_buf());
+            *self.pattern_hits.entry(m.pattern.clone()).or_insert(0) += 1;
+            *self.tier_hits.entry(m.tier).or_insert(0) += 1;
+        }
+        for (name, tier) in protected {
+            self.protected.entry(name).or_insert((tier, 0)).1 += 1;
         }
     }
-    FILES.lock().unwrap().extend(local_files);
-    DIRS.lock().unwrap().extend(local_dirs);
+
+    pub fn log_summary(&self, level: Tier) {
+        debug!("matched Contains: {} items", self.matches);
+        for tier in level.up_to() {
+            info!("Found {} files matching `{}` patterns", self.tier_hits.get(&tier).unwrap_or(&0), tier);
+        }
+        if !self.protected.is_empty() {
+            debug!("Protected packages:");
+            let mut protected: Vec<_> = self.protected.iter().collect();
+            protected.sort();
+            for (name, (tier, count)) in protected {
+                debug!("  - '{}': {} matching files kept (listed in `{}` ignore)", name, count, tier);
+            }
+        }
+        debug!("Pattern hit summary:");
+        for (pattern, count) in &self.pattern_hits {
+            debug!("  - '{}': {} matches", pattern, count);
+        }
+
+        if log_enabled!(Level::Debug) {
+            iter_pattern_hits(&self.pattern_hits);
+        }
+    }
+}
+
+// Keeps an error for the result and reports it right away
+fn record(errors: &mut Vec<ScanError>, observer: &dyn ScanObserver, e: ScanError) {
+    observer.on_error(&e);
+    errors.push(e);
 }
@@ -1,3 +1,8 @@
 pub mod fs_utils;
+pub mod index;
 pub mod matcher;
+pub mod plan;
+pub mod prune;
 pub mod remover;
+pub mod rules;
+pub mod trash;
@@ -0,0 +1,329 @@
+use chrono::Utc;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use crate::config::config::Tier;
+use crate::error::ScanError;
+use crate::file_utils::matcher::{PatternMatch, project_root};
+use crate::file_utils::remover::{RemovalMode, Remover};
+use crate::observer::ScanObserver;
+use crate::utils::read_size::bytes_to_mb;
+
+// Bumped whenever the plan layout changes in a way older readers can't handle
+pub const PLAN_VERSION: u32 = 1;
+
+/// One file the plan will remove, with what it looked like when planned.
+/// Rules only ever select files, so a plan never holds a directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    // Modification time in nanoseconds since the epoch
+    pub mtime_ns: u64,
+    pub tier: Tier,
+    pub pattern: String,
+    // Directory holding the outermost node_modules the path lives in
+    pub project: Option<PathBuf>,
+}
+
+/// A reviewed list of removals, written by `plan` and consumed by `apply`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    pub created: String,
+    pub level: Tier,
+    pub config: String,
+    pub roots: Vec<PathBuf>,
+    pub total_bytes: u64,
+    pub entries: Vec<PlanEntry>,
+}
+
+#[derive(Debug)]
+pub enum PlanError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    Version {
+        path: PathBuf,
+        found: u32,
+    },
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::Io { path, source } => {
+                write!(f, "Failed to access plan {}: {}", path.display(), source)
+            }
+            PlanError::Parse { path, source } => {
+                write!(f, "Failed to parse plan {}: {}", path.display(), source)
+            }
+            PlanError::Version { path, found } => write!(
+                f,
+                "Plan {} has version {}, this build only reads version {}",
+                path.display(),
+                found,
+                PLAN_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+/// Why a planned entry is no longer safe to remove
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift {
+    Vanished,
+    NotAFile,
+    SizeChanged { planned: u64, found: u64 },
+    MtimeChanged,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Vanished => write!(f, "no longer exists"),
+            Drift::NotAFile => write!(f, "is no longer a file"),
+            Drift::SizeChanged { planned, found } => {
+                write!(f, "size changed from {} to {} bytes", planned, found)
+            }
+            Drift::MtimeChanged => write!(f, "modified since the plan was written"),
+        }
+    }
+}
+
+fn mtime_ns(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+impl PlanEntry {
+    fn from_match(found: &PatternMatch) -> Result<Self, ScanError> {
+        let metadata = fs::symlink_metadata(&found.path).map_err(|e| {
+            warn!("Leaving {} out of the plan: {}", found.path.display(), e);
+            ScanError::from_io(&found.path, &e)
+        })?;
+
+        Ok(Self {
+            path: found.path.clone(),
+            size: found.size,
+            mtime_ns: mtime_ns(&metadata),
+            tier: found.tier,
+            pattern: found.pattern.clone(),
+            project: project_root(&found.path),
+        })
+    }
+
+    // Compares the entry with what is on disk right now, fails if that
+    // cannot be read
+    fn drift(&self) -> Result<Option<Drift>, ScanError> {
+        let metadata = match fs::symlink_metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Some(Drift::Vanished)),
+            Err(e) => return Err(ScanError::from_io(&self.path, &e)),
+        };
+
+        if !metadata.is_file() {
+            return Ok(Some(Drift::NotAFile));
+        }
+
+        let found = metadata.len();
+        if found != self.size {
+            return Ok(Some(Drift::SizeChanged {
+                planned: self.size,
+                found,
+            }));
+        }
+
+        if mtime_ns(&metadata) != self.mtime_ns {
+            return Ok(Some(Drift::MtimeChanged));
+        }
+        Ok(None)
+    }
+}
+
+impl Plan {
+    /// Matches that can no longer be read are left out and handed back
+    pub fn new(
+        level: Tier,
+        config: String,
+        roots: Vec<PathBuf>,
+        matches: &[PatternMatch],
+    ) -> (Self, Vec<ScanError>) {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        for m in matches {
+            match PlanEntry::from_match(m) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let plan = Self {
+            version: PLAN_VERSION,
+            created: Utc::now().to_rfc3339(),
+            level,
+            config,
+            roots,
+            total_bytes: entries.iter().map(|entry| entry.size).sum(),
+            entries,
+        };
+        (plan, errors)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), PlanError> {
+        let data = serde_json::to_string_pretty(self).map_err(|source| PlanError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        fs::write(path, data).map_err(|source| PlanError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, PlanError> {
+        let data = fs::read_to_string(path).map_err(|source| PlanError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let plan: Plan = serde_json::from_str(&data).map_err(|source| PlanError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        if plan.version != PLAN_VERSION {
+            return Err(PlanError::Version {
+                path: path.to_path_buf(),
+                found: plan.version,
+            });
+        }
+        Ok(plan)
+    }
+
+    /// Logs what the plan contains per tier
+    pub fn log_summary(&self) {
+        info!(
+            "Plan: {} entries, {} bytes ({:.2} MB), tiers up to `{}`",
+            self.entries.len(),
+            self.total_bytes,
+            bytes_to_mb(self.total_bytes),
+            self.level
+        );
+        for tier in Tier::ALL {
+            let (count, bytes) = self
+                .entries
+                .iter()
+                .filter(|entry| entry.tier == tier)
+                .fold((0, 0), |(count, bytes), entry| {
+                    (count + 1, bytes + entry.size)
+                });
+            if count > 0 {
+                info!("  {}: {} paths ({:.2} MB)", tier, count, bytes_to_mb(bytes));
+            }
+        }
+    }
+}
+
+/// A plan held against the disk right before it is applied
+#[derive(Debug)]
+pub struct PlanCheck<'a> {
+    // Entries still exactly as planned
+    pub ready: Vec<&'a PlanEntry>,
+    // Entries that vanished or changed since, never removed. They still
+    // fail the run, the plan was not applied as written
+    pub drifted: Vec<(&'a PlanEntry, Drift)>,
+    // Entries that could not be checked
+    pub errors: Vec<ScanError>,
+}
+
+impl Plan {
+    /// Checks every entry against what is on disk now and logs the ones
+    /// that will be kept
+    pub fn check(&self) -> PlanCheck<'_> {
+        let mut check = PlanCheck {
+            ready: Vec::new(),
+            drifted: Vec::new(),
+            errors: Vec::new(),
+        };
+        for entry in &self.entries {
+            match entry.drift() {
+                Ok(None) => check.ready.push(entry),
+                Ok(Some(drift)) => check.drifted.push((entry, drift)),
+                Err(e) => {
+                    warn!("Skipping {}, it could not be checked", entry.path.display());
+                    check.errors.push(e);
+                }
+            }
+        }
+
+        for (entry, drift) in &check.drifted {
+            warn!("Skipping {}: {}", entry.path.display(), drift);
+        }
+        if !check.drifted.is_empty() {
+            warn!(
+                "{} of {} planned entries changed and will be kept",
+                check.drifted.len(),
+                self.entries.len()
+            );
+        }
+
+        if !check.ready.is_empty() {
+            let ready_bytes: u64 = check.ready.iter().map(|entry| entry.size).sum();
+            info!(
+                "{} entries ({:.2} MB) still match the plan",
+                check.ready.len(),
+                bytes_to_mb(ready_bytes)
+            );
+        }
+        check
+    }
+}
+
+/// Removes the checked entries of a plan, once the caller confirmed it.
+/// Returns how many were removed and every path that could not be.
+pub fn apply_entries(
+    entries: &[&PlanEntry],
+    mode: &RemovalMode,
+    observer: Arc<dyn ScanObserver>,
+) -> (usize, Vec<ScanError>) {
+    let mut remover = match Remover::start(mode, observer) {
+        Ok(remover) => remover,
+        Err(e) => return (0, vec![e]),
+    };
+    let mut removed = 0;
+    let mut failed = 0;
+    for entry in entries {
+        let result = remover.remove(
+            &entry.path,
+            false,
+            entry.size,
+            Some(entry.tier),
+            &entry.pattern,
+        );
+        match result {
+            Ok(()) => removed += 1,
+            Err(_) => failed += 1,
+        }
+    }
+    if failed > 0 {
+        error!("Failed to remove {} planned entries", failed);
+    }
+    let (_, errors) = remover.finish();
+    (removed, errors)
+}
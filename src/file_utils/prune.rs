@@ -0,0 +1,230 @@
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+use crate::error::ScanError;
+use crate::file_utils::fs_utils::outermost_node_modules;
+use crate::file_utils::remover::{RemovalMode, Remover};
+use crate::observer::ScanObserver;
+use crate::utils::age::format_age;
+use crate::utils::read_size::{TreeStats, bytes_to_mb, get_tree_size};
+
+// Directories whose contents say nothing about whether a project is still
+// worked on: installed or generated output
+const GENERATED_DIRS: &[&str] = &["node_modules", "dist", "build", "coverage", "out"];
+// How deep below the project root source files are looked at
+const SOURCE_SCAN_DEPTH: usize = 6;
+// Recorded as the "pattern" of pruned trees in trash manifests
+const PRUNE_REASON: &str = "prune-projects";
+
+/// A node_modules tree and when its project was last touched
+#[derive(Debug, Clone)]
+pub struct ProjectActivity {
+    pub node_modules: PathBuf,
+    pub project: PathBuf,
+    // Newest mtime of package.json, lockfiles and source files
+    pub last_activity: Option<SystemTime>,
+    // The file that set `last_activity`
+    pub newest: Option<PathBuf>,
+    pub size: u64,
+    pub files: u64,
+}
+
+impl ProjectActivity {
+    /// None if no mtime could be read. A file dated in the future, after
+    /// clock skew or a checkout from another machine, counts as just touched.
+    pub fn age(&self, now: SystemTime) -> Option<Duration> {
+        self.last_activity
+            .map(|last| now.duration_since(last).unwrap_or_default())
+    }
+
+    fn is_stale(&self, stale: Duration, now: SystemTime) -> bool {
+        self.age(now).is_some_and(|age| age >= stale)
+    }
+}
+
+// Hidden and generated directories are not project activity
+fn is_activity_dir(name: &str) -> bool {
+    !name.starts_with('.') && !GENERATED_DIRS.contains(&name)
+}
+
+// Newest file of the project outside node_modules, hidden and generated
+// directories. package.json and the lockfiles live at the root and are
+// always included.
+fn newest_project_file(project: &Path) -> Option<(SystemTime, PathBuf)> {
+    WalkDir::new(project)
+        .max_depth(SOURCE_SCAN_DEPTH)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !entry.file_type().is_dir()
+                || is_activity_dir(&entry.file_name().to_string_lossy())
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, entry.into_path()))
+        })
+        .max_by_key(|(modified, _)| *modified)
+}
+
+/// Looks at the project owning `node_modules`, sized as `tree`. Projects
+/// without any file of their own fall back to the mtime of the node_modules
+/// directory.
+pub fn project_activity(node_modules: &Path, tree: &TreeStats) -> ProjectActivity {
+    let project = node_modules
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| node_modules.to_path_buf());
+
+    let (last_activity, newest) = match newest_project_file(&project) {
+        Some((modified, path)) => (Some(modified), Some(path)),
+        None => (
+            fs::metadata(node_modules).and_then(|m| m.modified()).ok(),
+            None,
+        ),
+    };
+
+    ProjectActivity {
+        node_modules: node_modules.to_path_buf(),
+        project,
+        last_activity,
+        newest,
+        size: tree.bytes,
+        files: tree.files,
+    }
+}
+
+/// Activity of every outermost node_modules, least recently touched first.
+/// The sizes come from `trees`, as a scan tallied them, only node_modules
+/// missing there are read again. Returns every path that could not be sized.
+pub fn rank_projects(
+    node_modules: &[PathBuf],
+    trees: &[(PathBuf, TreeStats)],
+) -> (Vec<ProjectActivity>, Vec<ScanError>) {
+    let sized: HashMap<&Path, &TreeStats> = trees
+        .iter()
+        .map(|(path, tree)| (path.as_path(), tree))
+        .collect();
+    let mut errors = Vec::new();
+    let mut projects: Vec<ProjectActivity> = outermost_node_modules(node_modules)
+        .iter()
+        .map(|path| match sized.get(path.as_path()) {
+            Some(tree) => project_activity(path, tree),
+            None => {
+                let (tree, sizing_errors) = get_tree_size(path);
+                errors.extend(sizing_errors);
+                project_activity(path, &tree)
+            }
+        })
+        .collect();
+
+    // Undated projects sort first and are listed on their own
+    projects.sort_by_key(|project| project.last_activity);
+    (projects, errors)
+}
+
+/// Writes the ranking to `out`, marking the projects untouched for at
+/// least `stale`. Undated projects are listed on their own.
+pub fn print_projects(
+    out: &mut impl Write,
+    projects: &[ProjectActivity],
+    stale: Duration,
+    now: SystemTime,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "  {:>6} {:>10} {:>8} NODE_MODULES",
+        "AGE", "SIZE (MB)", "FILES"
+    )?;
+    for project in projects {
+        let Some(age) = project.age(now) else {
+            continue;
+        };
+        let marker = if project.is_stale(stale, now) {
+            '*'
+        } else {
+            ' '
+        };
+        writeln!(
+            out,
+            "{} {:>6} {:>10.2} {:>8} {}",
+            marker,
+            format_age(age),
+            bytes_to_mb(project.size),
+            project.files,
+            project.node_modules.display()
+        )?;
+        if let Some(newest) = &project.newest {
+            debug!("    last touched: {}", newest.display());
+        }
+    }
+
+    let undated: Vec<&ProjectActivity> = projects
+        .iter()
+        .filter(|project| project.last_activity.is_none())
+        .collect();
+    if !undated.is_empty() {
+        writeln!(
+            out,
+            "\nNo readable mtime, only removed with --include-undated:"
+        )?;
+        for project in undated {
+            writeln!(
+                out,
+                "  {:>6} {:>10.2} {:>8} {}",
+                "?",
+                bytes_to_mb(project.size),
+                project.files,
+                project.node_modules.display()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The projects untouched for at least `stale`. Projects whose age is
+/// unknown are only included with `include_undated`.
+pub fn stale_projects(
+    projects: &[ProjectActivity],
+    stale: Duration,
+    include_undated: bool,
+    now: SystemTime,
+) -> Vec<&ProjectActivity> {
+    projects
+        .iter()
+        .filter(|project| {
+            project.is_stale(stale, now) || (include_undated && project.last_activity.is_none())
+        })
+        .collect()
+}
+
+/// Removes the whole node_modules trees of `projects`, once the caller
+/// confirmed it. Returns the trees that could not be removed.
+pub fn remove_projects(
+    projects: &[&ProjectActivity],
+    mode: &RemovalMode,
+    observer: Arc<dyn ScanObserver>,
+) -> Vec<ScanError> {
+    let mut remover = match Remover::start(mode, observer) {
+        Ok(remover) => remover,
+        Err(e) => return vec![e],
+    };
+    for project in projects {
+        let _ = remover.remove(
+            &project.node_modules,
+            true,
+            project.size,
+            None,
+            PRUNE_REASON,
+        );
+    }
+    let (_, errors) = remover.finish();
+    errors
+}
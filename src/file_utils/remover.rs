@@ -1,53 +1,184 @@
-use crate::config::cli::ask_yes_no;
-use crate::utils::read_size::{get_paths_size};
+use crate::config::config::Tier;
+use crate::error::ScanError;
+use crate::file_utils::matcher::PatternMatch;
+use crate::file_utils::trash::TrashSession;
+use crate::observer::ScanObserver;
+use crate::utils::read_size::bytes_to_mb;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use log::{info, error, warn};
 
-pub fn remove_file_on_path(files: Vec<PathBuf>, dirs: Vec<PathBuf>, debug_mode: bool) {
-    // Combine all paths for a single size calculation
-    let mut all_paths = Vec::with_capacity(files.len() + dirs.len());
-    all_paths.extend(files.iter().cloned());
-    all_paths.extend(dirs.iter().cloned());
-
-    match get_paths_size(&all_paths) {
-        Ok((total_bytes, total_mb)) => {
-            info!("Total target size: {} bytes ({:.2} MB)", total_bytes, total_mb);
-            info!("Files: {}, Directories: {}", files.len(), dirs.len());
-        }
-        Err(e) => {
-            error!("Failed to calculate total size: {}", e);
+// Logs the size of everything matched per tier, as recorded by the scan
+fn log_tier_totals(matches: &[PatternMatch]) {
+    for tier in Tier::ALL {
+        let tier_matches: Vec<&PatternMatch> = matches.iter().filter(|m| m.tier == tier).collect();
+        if tier_matches.is_empty() {
+            continue;
         }
+
+        let tier_bytes: u64 = tier_matches.iter().map(|m| m.size).sum();
+        info!("  {}: {} paths ({:.2} MB)", tier, tier_matches.len(), bytes_to_mb(tier_bytes));
     }
+}
+
+// Logs how much the matches add up to, in total and per tier, as recorded by the scan
+pub fn log_summary(matches: &[PatternMatch]) {
+    let total_bytes: u64 = matches.iter().map(|m| m.size).sum();
+    info!("Total target size: {} bytes ({:.2} MB)", total_bytes, bytes_to_mb(total_bytes));
+    info!("Files: {}", matches.len());
+    log_tier_totals(matches);
+}
 
-    if debug_mode {
-        warn!("Debug mode is ON. No files will be deleted.");
-        return;
+// Removes the files the rules matched, once the caller confirmed it.
+// Returns every path that could not be removed
+pub fn remove_matches(matches: Vec<PatternMatch>, mode: &RemovalMode, observer: Arc<dyn ScanObserver>) -> Vec<ScanError> {
+    let mut remover = match Remover::start(mode, observer) {
+        Ok(remover) => remover,
+        Err(e) => return vec![e],
+    };
+    for m in matches {
+        let _ = remover.remove(&m.path, false, m.size, Some(m.tier), &m.pattern);
     }
+    let (_, errors) = remover.finish();
+    errors
+}
 
-    if ask_yes_no("About to permanently remove files and directories from your system. Proceed?") {
-        for file in files {
-            if file.is_file() {
-                match fs::remove_file(&file) {
-                    Ok(_) => info!("Removed file: {}", file.display()),
-                    Err(e) => error!("Failed to remove file {}: {}", file.display(), e),
-                }
-            } else {
-                warn!("Not a valid file: {}", file.display());
-            }
+// What happens to a path once removal is confirmed
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemovalMode {
+    Delete,
+    // Move into a new quarantine session below this trash directory
+    Trash(PathBuf),
+}
+
+impl RemovalMode {
+    pub fn confirm_prompt(&self) -> &'static str {
+        match self {
+            RemovalMode::Delete => "About to permanently remove files and directories from your system. Proceed?",
+            RemovalMode::Trash(_) => "About to move files and directories to the trash. Proceed?",
         }
+    }
+}
+
+// Removes paths one by one, either for good or into a trash session
+pub struct Remover {
+    trash: Option<TrashSession>,
+    // Paths that could not be removed, handed back by `finish`
+    errors: Vec<ScanError>,
+    // Told about every removed and every failed path as it happens
+    observer: Arc<dyn ScanObserver>,
+}
 
-        for dir in dirs {
-            if dir.is_dir() {
-                match fs::remove_dir_all(&dir) {
-                    Ok(_) => info!("Removed directory: {}", dir.display()),
-                    Err(e) => error!("Failed to remove directory {}: {}", dir.display(), e),
+impl Remover {
+    // Fails if the trash session could not be created, nothing is touched then
+    pub fn start(mode: &RemovalMode, observer: Arc<dyn ScanObserver>) -> Result<Self, ScanError> {
+        let trash = match mode {
+            RemovalMode::Delete => None,
+            RemovalMode::Trash(dir) => match TrashSession::create(dir) {
+                Ok(session) => Some(session),
+                Err(e) => {
+                    error!("Failed to create trash session in {}: {}", dir.display(), e);
+                    let e = ScanError::from_trash(&e);
+                    observer.on_error(&e);
+                    return Err(e);
                 }
-            } else {
-                warn!("Not a valid directory: {}", dir.display());
+            },
+        };
+        Ok(Self { trash, errors: Vec::new(), observer })
+    }
+
+    // Removes or stashes a single file or directory. A failure is also kept
+    // for `finish`. `tier` is None for paths removed as a whole rather than by a rule.
+    pub fn remove(&mut self, path: &Path, is_dir: bool, size: u64, tier: Option<Tier>, pattern: &str) -> Result<(), ScanError> {
+        let result = match &mut self.trash {
+            None => remove_path(path, tier, is_dir),
+            Some(session) => stash_path(session, path, is_dir, size, tier, pattern),
+        };
+        match &result {
+            Ok(()) => self.observer.on_removed(path, size),
+            Err(e) => { self.observer.on_error(e); self.errors.push(e.clone()); }
+        }
+        result
+    }
+
+    // Writes the trash manifest and tells the user how to undo.
+    // Returns the trash session id, if anything was moved to the trash, and
+    // every path that could not be removed.
+    pub fn finish(mut self) -> (Option<String>, Vec<ScanError>) {
+        let Some(session) = self.trash else {
+            return (None, self.errors);
+        };
+        let count = session.len();
+        let id = match session.finish() {
+            Ok(Some(id)) => {
+                info!("Moved {} paths to trash session {}, undo with `node-cleaner restore {}`", count, id, id);
+                Some(id)
             }
+            Ok(None) => { warn!("Nothing was moved to the trash."); None }
+            Err(e) => {
+                error!("Failed to write trash manifest: {}", e);
+                let e = ScanError::from_trash(&e);
+                self.observer.on_error(&e);
+                self.errors.push(e);
+                None
+            }
+        };
+        (id, self.errors)
+    }
+}
+
+fn stash_path(session: &mut TrashSession, path: &Path, is_dir: bool, size: u64, tier: Option<Tier>, pattern: &str) -> Result<(), ScanError> {
+    let valid = if is_dir { path.is_dir() } else { path.is_file() };
+    if !valid {
+        warn!("Not a valid {}: {}", kind_name(is_dir), path.display());
+        return Err(invalid_path(path, is_dir));
+    }
+    match session.stash(path, is_dir, size, tier, pattern) {
+        Ok(_) => { info!("Trashed {}: {}", describe(tier, is_dir), path.display()); Ok(()) }
+        Err(e) => { error!("Failed to move to trash: {}", e); Err(ScanError::from_trash(&e)) }
+    }
+}
+
+// A path that is gone, or is no longer the kind of entry it was matched as
+fn invalid_path(path: &Path, is_dir: bool) -> ScanError {
+    match fs::symlink_metadata(path) {
+        Ok(_) => ScanError::Io { path: path.to_path_buf(), message: format!("not a {}", kind_name(is_dir)) },
+        Err(e) => ScanError::from_io(path, &e),
+    }
+}
+
+fn kind_name(is_dir: bool) -> &'static str {
+    if is_dir { "directory" } else { "file" }
+}
+
+// `safe file`, or just `directory` for paths without a tier
+fn describe(tier: Option<Tier>, is_dir: bool) -> String {
+    match tier {
+        Some(tier) => format!("{} {}", tier, kind_name(is_dir)),
+        None => kind_name(is_dir).to_string(),
+    }
+}
+
+// Removes a single file or directory for good
+pub fn remove_path(path: &Path, tier: Option<Tier>, is_dir: bool) -> Result<(), ScanError> {
+    if is_dir {
+        if !path.is_dir() {
+            warn!("Not a valid directory: {}", path.display());
+            return Err(invalid_path(path, is_dir));
+        }
+        match fs::remove_dir_all(path) {
+            Ok(_) => { info!("Removed {}: {}", describe(tier, is_dir), path.display()); Ok(()) }
+            Err(e) => { error!("Failed to remove directory {}: {}", path.display(), e); Err(ScanError::from_io(path, &e)) }
         }
     } else {
-        warn!("User aborted deletion.");
+        if !path.is_file() {
+            warn!("Not a valid file: {}", path.display());
+            return Err(invalid_path(path, is_dir));
+        }
+        match fs::remove_file(path) {
+            Ok(_) => { info!("Removed {}: {}", describe(tier, is_dir), path.display()); Ok(()) }
+            Err(e) => { error!("Failed to remove file {}: {}", path.display(), e); Err(ScanError::from_io(path, &e)) }
+        }
     }
 }
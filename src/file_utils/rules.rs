@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::config::{Config, Tier};
+use crate::file_utils::matcher::owning_packages;
+use crate::utils::glob::{Glob, GlobError};
+
+/// A single pattern from a rule set, compiled once.
+///
+/// Patterns are matched case-insensitively against the path of a file
+/// relative to the package that owns it (see [`rule_relative_path`]):
+/// - `name`, `*.md`, `readme*` match any path component, so a directory
+///   match covers every file below it
+/// - a leading `/` or a `/` in the middle anchors the pattern to the package root
+/// - a trailing `/` only matches directories, never the file name itself
+/// - `*`, `?`, `[...]` and `**` work as in gitignore
+/// - a leading `!` negates the pattern: within the same tier the last
+///   matching pattern decides, so `!pattern` keeps files an earlier pattern
+///   selected (use `\!` for a literal `!`)
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub tier: Tier,
+    pub pattern: String,
+    pub negated: bool,
+    glob: Glob,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl Rule {
+    pub fn compile(pattern: &str, tier: Tier) -> Result<Self, GlobError> {
+        let trimmed = pattern.trim();
+        let (negated, trimmed) = match trimmed.strip_prefix('!') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, trimmed),
+        };
+        let dir_only = trimmed.ends_with('/');
+        let body = trimmed.trim_end_matches('/');
+        let anchored = body.contains('/');
+
+        Ok(Self {
+            tier,
+            pattern: pattern.to_string(),
+            negated,
+            glob: Glob::new_case_insensitive(body)?,
+            anchored,
+            dir_only,
+        })
+    }
+
+    /// `segments` must be lowercased, the last segment is the file name
+    pub fn matches(&self, segments: &[&str]) -> bool {
+        // Directory-only rules may not match the file name itself
+        let candidates = if self.dir_only {
+            &segments[..segments.len().saturating_sub(1)]
+        } else {
+            segments
+        };
+        if candidates.is_empty() {
+            return false;
+        }
+
+        if self.anchored || !self.glob.is_single_segment() {
+            self.glob.match_prefix_len(candidates).is_some()
+        } else {
+            candidates
+                .iter()
+                .any(|segment| self.glob.matches_name(segment))
+        }
+    }
+}
+
+/// Every rule of every tier up to a level, compiled once and kept in order:
+/// tiers safest first, patterns in config order
+#[derive(Debug, Clone)]
+pub struct RuleMatcher {
+    level: Tier,
+    rules: Vec<Rule>,
+    // Package name -> tier whose `ignore` list protects it
+    ignored_packages: HashMap<String, Tier>,
+}
+
+// Everything that decides what the rules select, see `index::fingerprint`
+impl Hash for RuleMatcher {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.level.hash(state);
+        for rule in &self.rules {
+            (rule.tier, &rule.pattern).hash(state);
+        }
+        let mut ignored: Vec<_> = self.ignored_packages.iter().collect();
+        ignored.sort();
+        ignored.hash(state);
+    }
+}
+
+impl RuleMatcher {
+    pub fn new(config: &Config, level: Tier) -> Result<Self, GlobError> {
+        let mut rules = Vec::new();
+        let mut ignored_packages = HashMap::new();
+        for (tier, ruleset) in config.rule_sets_up_to(level) {
+            for pattern in &ruleset.patterns {
+                rules.push(Rule::compile(pattern, tier)?);
+            }
+            // Packages listed in any active tier's `ignore` are protected from every tier
+            for name in &ruleset.ignore {
+                ignored_packages.entry(name.clone()).or_insert(tier);
+            }
+        }
+        Ok(Self {
+            level,
+            rules,
+            ignored_packages,
+        })
+    }
+
+    pub fn level(&self) -> Tier {
+        self.level
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The ignored package `path` lives in, with the tier that lists it
+    pub fn protected_by(&self, path: &Path) -> Option<(String, Tier)> {
+        if self.ignored_packages.is_empty() {
+            return None;
+        }
+        owning_packages(path).into_iter().find_map(|package| {
+            self.ignored_packages
+                .get(&package.name)
+                .map(|tier| (package.name, *tier))
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The rule selecting a relative path for removal, the path is lowercased
+    /// here. Tiers are tried safest first; inside a tier the last matching
+    /// rule decides and a negated rule means the tier keeps the file.
+    pub fn find(&self, relative_path: &str) -> Option<&Rule> {
+        let lowered = relative_path.to_lowercase();
+        let segments: Vec<&str> = lowered.split('/').filter(|s| !s.is_empty()).collect();
+        self.rules
+            .chunk_by(|a, b| a.tier == b.tier)
+            .find_map(|tier_rules| {
+                deciding_rule(tier_rules, &segments).filter(|rule| !rule.negated)
+            })
+    }
+
+    /// Every rule that matches `path` and which one won, used by `--explain`
+    pub fn explain(&self, path: &Path) -> Explanation<'_> {
+        let relative_path = rule_relative_path(path);
+        let lowered = relative_path.to_lowercase();
+        let segments: Vec<&str> = lowered.split('/').filter(|s| !s.is_empty()).collect();
+
+        let tiers = self
+            .rules
+            .chunk_by(|a, b| a.tier == b.tier)
+            .map(|tier_rules| TierExplanation {
+                tier: tier_rules[0].tier,
+                matched: tier_rules
+                    .iter()
+                    .filter(|rule| rule.matches(&segments))
+                    .collect(),
+                decided_by: deciding_rule(tier_rules, &segments),
+            })
+            .collect();
+
+        Explanation {
+            path: path.to_path_buf(),
+            packages: owning_packages(path).into_iter().map(|p| p.name).collect(),
+            relative_path,
+            protected_by: self.protected_by(path),
+            winner: self.find(&lowered),
+            tiers,
+        }
+    }
+}
+
+// The last rule of a single tier that matches
+fn deciding_rule<'a>(tier_rules: &'a [Rule], segments: &[&str]) -> Option<&'a Rule> {
+    tier_rules.iter().rev().find(|rule| rule.matches(segments))
+}
+
+pub struct TierExplanation<'a> {
+    pub tier: Tier,
+    pub matched: Vec<&'a Rule>,
+    pub decided_by: Option<&'a Rule>,
+}
+
+/// Why a path is or is not selected, see [`RuleMatcher::explain`]
+pub struct Explanation<'a> {
+    pub path: PathBuf,
+    pub packages: Vec<String>,
+    pub relative_path: String,
+    pub protected_by: Option<(String, Tier)>,
+    pub winner: Option<&'a Rule>,
+    pub tiers: Vec<TierExplanation<'a>>,
+}
+
+impl Explanation<'_> {
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Path:     {}", self.path.display())?;
+        if self.packages.is_empty() {
+            writeln!(out, "Package:  (none)")?;
+        } else {
+            writeln!(out, "Package:  {}", self.packages.join(" > "))?;
+        }
+        writeln!(out, "Matched:  {}", self.relative_path)?;
+
+        for tier in &self.tiers {
+            if tier.matched.is_empty() {
+                writeln!(out, "{}: no matching rule", tier.tier)?;
+                continue;
+            }
+            writeln!(out, "{}:", tier.tier)?;
+            for rule in &tier.matched {
+                let decided = tier
+                    .decided_by
+                    .is_some_and(|decided| std::ptr::eq(decided, *rule));
+                let note = match (decided, rule.negated) {
+                    (true, true) => "  <- decides, keeps the file",
+                    (true, false) => "  <- decides, selects the file",
+                    _ => "",
+                };
+                writeln!(out, "  {}{}", rule.pattern, note)?;
+            }
+        }
+
+        match (&self.protected_by, self.winner) {
+            (Some((name, tier)), Some(_)) => writeln!(
+                out,
+                "Result:   kept, package '{}' is listed in the `{}` ignore list",
+                name, tier
+            ),
+            (_, Some(rule)) => writeln!(
+                out,
+                "Result:   removed as `{}` by rule '{}'",
+                rule.tier, rule.pattern
+            ),
+            (_, None) => writeln!(out, "Result:   kept, no tier selects it"),
+        }
+    }
+}
+
+/// The part of `path` rules are matched against: relative to the innermost
+/// package root, or to the innermost `node_modules` for entries that are not
+/// inside a package (like `node_modules/.bin/tsc`)
+pub fn rule_relative_path(path: &Path) -> String {
+    let base = match owning_packages(path).pop() {
+        Some(package) => package.root,
+        None => {
+            let mut base = path.to_path_buf();
+            while let Some(name) = base.file_name() {
+                if name == "node_modules" {
+                    break;
+                }
+                if !base.pop() {
+                    break;
+                }
+            }
+            base
+        }
+    };
+
+    let relative = path.strip_prefix(&base).unwrap_or(path);
+    relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config::{ConfigSource, EMBEDDED_PATTERNS, parse_config};
+
+    fn embedded(level: Tier) -> RuleMatcher {
+        let config = parse_config(EMBEDDED_PATTERNS, &ConfigSource::Embedded).unwrap();
+        RuleMatcher::new(&config, level).unwrap()
+    }
+
+    fn rule(pattern: &str) -> Rule {
+        Rule::compile(pattern, Tier::Safe).unwrap()
+    }
+
+    fn matches(rule: &Rule, path: &str) -> bool {
+        let lowered = path.to_lowercase();
+        let segments: Vec<&str> = lowered.split('/').collect();
+        rule.matches(&segments)
+    }
+
+    fn matched_pattern(matcher: &RuleMatcher, path: &str) -> Option<String> {
+        let relative = rule_relative_path(&PathBuf::from(path));
+        matcher.find(&relative).map(|rule| rule.pattern.clone())
+    }
+
+    #[test]
+    fn plain_names_match_any_component() {
+        let test = rule("test");
+        assert!(matches(&test, "test"));
+        assert!(matches(&test, "test/unit/a.js"));
+        assert!(matches(&test, "lib/test/a.js"));
+        assert!(!matches(&test, "lib/testing/a.js"));
+        assert!(!matches(&test, "test.js"));
+    }
+
+    #[test]
+    fn wildcards_and_case() {
+        assert!(matches(&rule("readme*"), "README.md"));
+        assert!(matches(&rule("readme*"), "docs/Readme"));
+        assert!(!matches(&rule("readme*"), "docs/old-readme.md"));
+        assert!(matches(&rule("*.md"), "docs/guide.MD"));
+        assert!(matches(
+            &rule("*.vcxproj*"),
+            "build/binding.vcxproj.filters"
+        ));
+        assert!(matches(&rule("*.vcxproj*"), "binding.vcxproj"));
+        assert!(!matches(&rule("*.vcxproj*"), "binding.sln"));
+        assert!(matches(&rule(".sauce-labs*"), ".sauce-labs.conf.js"));
+        assert!(matches(&rule(".eslintrc.*"), ".eslintrc.json"));
+        assert!(!matches(&rule(".eslintrc.*"), ".eslintrc"));
+    }
+
+    #[test]
+    fn question_mark_and_classes() {
+        assert!(matches(&rule("draft-0?"), "draft-04/schema.json"));
+        assert!(!matches(&rule("draft-0?"), "draft-10/schema.json"));
+        assert!(matches(&rule("*.[ch]"), "src/binding.c"));
+        assert!(matches(&rule("*.[ch]"), "src/binding.h"));
+        assert!(!matches(&rule("*.[ch]"), "src/binding.cc"));
+        assert!(matches(&rule("*.[!j]s"), "index.ts"));
+        assert!(!matches(&rule("*.[!j]s"), "index.js"));
+        assert!(matches(&rule("v[0-9]"), "v3/a.js"));
+    }
+
+    #[test]
+    fn anchored_and_directory_only() {
+        let docs = rule("/docs");
+        assert!(matches(&docs, "docs/api.md"));
+        assert!(!matches(&docs, "lib/docs/api.md"));
+
+        let nested = rule("lib/*.map");
+        assert!(matches(&nested, "lib/index.js.map"));
+        assert!(!matches(&nested, "dist/lib/index.js.map"));
+
+        let dir = rule("fixtures/");
+        assert!(matches(&dir, "test/fixtures/a.json"));
+        assert!(!matches(&dir, "fixtures"));
+
+        let deep = rule("**/fixtures/*.json");
+        assert!(matches(&deep, "a/b/fixtures/x.json"));
+        assert!(!matches(&deep, "a/b/fixtures/x.js"));
+
+        let anywhere = rule("dist/**/*.map");
+        assert!(matches(&anywhere, "dist/index.js.map"));
+        assert!(matches(&anywhere, "dist/esm/deep/index.js.map"));
+        assert!(!matches(&anywhere, "lib/dist/index.js.map"));
+    }
+
+    #[test]
+    fn relative_path_is_taken_from_the_innermost_package() {
+        assert_eq!(
+            rule_relative_path(Path::new("/p/node_modules/a/lib/test/x.js")),
+            "lib/test/x.js"
+        );
+        assert_eq!(
+            rule_relative_path(Path::new("/p/node_modules/@s/b/README.md")),
+            "README.md"
+        );
+        assert_eq!(
+            rule_relative_path(Path::new("/p/node_modules/a/node_modules/b/index.js")),
+            "index.js"
+        );
+        assert_eq!(
+            rule_relative_path(Path::new("/p/node_modules/.bin/tsc")),
+            ".bin/tsc"
+        );
+        // Directories above node_modules never take part in matching
+        assert_eq!(
+            rule_relative_path(Path::new("/home/test/node_modules/a/index.js")),
+            "index.js"
+        );
+    }
+
+    #[test]
+    fn embedded_safe_rules() {
+        let matcher = embedded(Tier::Safe);
+        let root = "/home/u/app/node_modules";
+
+        for (file, pattern) in [
+            ("lodash/README", "readme*"),
+            ("lodash/LICENSE", "license"),
+            ("express/History.md", "*.md"),
+            ("helmet/example/basic.js", "example*"),
+            ("ajv/lib/refs/draft-04/schema.json", "draft-04"),
+            ("nan/build/binding.vcxproj.filters", "*.vcxproj*"),
+            ("chai/.sauce-labs.conf", ".sauce-labs*"),
+            ("qs/.eslintrc.yml", ".eslintrc.*"),
+            ("source-map/dist/source-map.js.map", "*.js.map"),
+            ("mocha/test/unit.js", "test"),
+            ("@babel/core/CHANGELOG", "changelog*"),
+        ] {
+            assert_eq!(
+                matched_pattern(&matcher, &format!("{}/{}", root, file)).as_deref(),
+                Some(pattern),
+                "{}",
+                file
+            );
+        }
+
+        for file in [
+            "lodash/lodash.js",
+            "lodash/package.json",
+            "react/index.js",
+            "typescript/lib/typescript.d.ts",
+            "test/index.js",
+        ] {
+            assert_eq!(
+                matched_pattern(&matcher, &format!("{}/{}", root, file)),
+                None,
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn safer_tiers_win() {
+        let matcher = embedded(Tier::Danger);
+        let root = "/app/node_modules";
+
+        let find = |file: &str| {
+            let relative = rule_relative_path(&PathBuf::from(format!("{}/{}", root, file)));
+            matcher
+                .find(&relative)
+                .map(|rule| (rule.tier, rule.pattern.clone()))
+        };
+
+        assert_eq!(find("a/README.md"), Some((Tier::Safe, "*.md".into())));
+        assert_eq!(find("a/logo.png"), Some((Tier::Caution, "*.png".into())));
+        assert_eq!(
+            find("a/index.min.js"),
+            Some((Tier::Danger, "*.min.js".into()))
+        );
+        assert_eq!(find(".bin/tsc"), Some((Tier::Danger, ".bin".into())));
+        // `*.sh` is listed in both caution and danger
+        assert_eq!(find("a/install.sh"), Some((Tier::Caution, "*.sh".into())));
+        assert_eq!(embedded(Tier::Safe).find("logo.png").map(|r| r.tier), None);
+    }
+
+    #[test]
+    fn negation_keeps_files_within_the_same_tier() {
+        let config = parse_config(
+            r#"{
+                "$default": "caution",
+                "safe": {
+                    "patterns": ["test", "example*", "!test/fixtures/", "!examples/*.json", "test/fixtures/*.tmp"],
+                    "ignore": []
+                },
+                "caution": { "patterns": ["*.json"], "ignore": [] }
+            }"#,
+            &ConfigSource::Embedded,
+        )
+        .unwrap();
+
+        let safe = RuleMatcher::new(&config, Tier::Safe).unwrap();
+        let find = |matcher: &RuleMatcher, path: &str| {
+            matcher
+                .find(path)
+                .map(|rule| (rule.tier, rule.pattern.clone()))
+        };
+
+        assert_eq!(find(&safe, "test/a.js"), Some((Tier::Safe, "test".into())));
+        assert_eq!(find(&safe, "test/fixtures/a.js"), None);
+        // A later positive pattern overrides the earlier negation
+        assert_eq!(
+            find(&safe, "test/fixtures/cache.tmp"),
+            Some((Tier::Safe, "test/fixtures/*.tmp".into()))
+        );
+        assert_eq!(find(&safe, "examples/schema.json"), None);
+        assert_eq!(
+            find(&safe, "examples/run.js"),
+            Some((Tier::Safe, "example*".into()))
+        );
+
+        // Negations only apply to their own tier
+        let caution = RuleMatcher::new(&config, Tier::Caution).unwrap();
+        assert_eq!(
+            find(&caution, "examples/schema.json"),
+            Some((Tier::Caution, "*.json".into()))
+        );
+
+        let explanation = caution.explain(Path::new("/p/node_modules/a/examples/schema.json"));
+        assert_eq!(explanation.relative_path, "examples/schema.json");
+        assert_eq!(
+            explanation.tiers[0]
+                .decided_by
+                .map(|rule| rule.pattern.as_str()),
+            Some("!examples/*.json")
+        );
+        assert_eq!(
+            explanation.winner.map(|rule| rule.tier),
+            Some(Tier::Caution)
+        );
+    }
+}
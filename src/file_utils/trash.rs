@@ -0,0 +1,630 @@
+use chrono::{DateTime, Local, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::config::Tier;
+use crate::error::ScanError;
+use crate::file_utils::fs_utils::write_atomic;
+
+// Bumped whenever the manifest layout changes in a way older readers can't handle
+pub const MANIFEST_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+// What an unfinished run did, so it can still be restored: every stashed
+// path is announced before it is moved and settled after. Folded into the
+// manifest once the session is finished.
+const JOURNAL_FILE: &str = "journal.ndjson";
+// Quarantined paths live under `<session>/files/<absolute path>`
+const FILES_DIR: &str = "files";
+
+/// `$XDG_DATA_HOME/node-cleaner/trash`, or `~/.local/share/node-cleaner/trash`
+pub fn default_trash_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("node-cleaner")
+        .join("trash")
+}
+
+#[derive(Debug)]
+pub enum TrashError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // `rename` cannot move across filesystems and we never copy
+    CrossDevice {
+        path: PathBuf,
+        trash: PathBuf,
+    },
+    Manifest {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    UnknownSession(String),
+}
+
+impl fmt::Display for TrashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrashError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            TrashError::CrossDevice { path, trash } => write!(
+                f,
+                "{} is on another filesystem than {}, pass a --trash-dir on the same filesystem",
+                path.display(),
+                trash.display()
+            ),
+            TrashError::Manifest { path, source } => {
+                write!(f, "Failed to read manifest {}: {}", path.display(), source)
+            }
+            TrashError::UnknownSession(session) => write!(f, "No trash session '{}'", session),
+        }
+    }
+}
+
+impl std::error::Error for TrashError {}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> TrashError + '_ {
+    move |source| TrashError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// One quarantined path and where it came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub original: PathBuf,
+    // Relative to the session's `files` directory
+    pub stored: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    // None for whole trees removed by `prune-projects`
+    #[serde(default)]
+    pub tier: Option<Tier>,
+    pub pattern: String,
+}
+
+// One line of the journal. An intent nothing settled is decided by whether
+// its path made it into `files`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum JournalRecord {
+    Intent(TrashEntry),
+    Commit { stored: PathBuf },
+    Abort { stored: PathBuf },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub session: String,
+    pub created: String,
+    pub entries: Vec<TrashEntry>,
+}
+
+impl Manifest {
+    // The manifest plus whatever the journal of an unfinished run adds to it
+    fn read(session_dir: &Path) -> Result<Self, TrashError> {
+        let path = session_dir.join(MANIFEST_FILE);
+        let data = fs::read_to_string(&path).map_err(io_error(&path))?;
+        let mut manifest: Manifest =
+            serde_json::from_str(&data).map_err(|source| TrashError::Manifest { path, source })?;
+
+        let journal = session_dir.join(JOURNAL_FILE);
+        let data = match fs::read_to_string(&journal) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(manifest),
+            Err(e) => return Err(io_error(&journal)(e)),
+        };
+        // A run killed mid-write leaves a torn last line, its path was
+        // moved but never recorded and has to be found by hand. Replaying
+        // twice changes nothing: a run killed after writing the manifest but
+        // before dropping the journal only finds entries it already has, and
+        // entries no longer in `files` were restored since.
+        let mut intents = Vec::new();
+        let mut aborted = HashSet::new();
+        for line in data.lines() {
+            match serde_json::from_str::<JournalRecord>(line) {
+                Ok(JournalRecord::Intent(entry)) => intents.push(entry),
+                Ok(JournalRecord::Commit { .. }) => {}
+                Ok(JournalRecord::Abort { stored }) => {
+                    aborted.insert(stored);
+                }
+                Err(e) => warn!("Skipping a broken line of {}: {}", journal.display(), e),
+            }
+        }
+        let files = session_dir.join(FILES_DIR);
+        for entry in intents {
+            if !aborted.contains(&entry.stored)
+                && !manifest.entries.iter().any(|e| e.stored == entry.stored)
+                && fs::symlink_metadata(files.join(&entry.stored)).is_ok()
+            {
+                manifest.entries.push(entry);
+            }
+        }
+        Ok(manifest)
+    }
+
+    // Replaces the manifest in one rename, the journal is part of it from then on
+    fn write(&self, session_dir: &Path) -> Result<(), TrashError> {
+        let path = session_dir.join(MANIFEST_FILE);
+        let data = serde_json::to_vec_pretty(self).map_err(|source| TrashError::Manifest {
+            path: path.clone(),
+            source,
+        })?;
+        write_atomic(&path, &data).map_err(io_error(&path))?;
+
+        let journal = session_dir.join(JOURNAL_FILE);
+        match fs::remove_file(&journal) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(&journal)(e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn created_at(&self) -> Option<SystemTime> {
+        DateTime::parse_from_rfc3339(&self.created)
+            .ok()
+            .map(|created| SystemTime::from(created.with_timezone(&Utc)))
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+}
+
+// `/home/u/x` becomes `home/u/x` so it can be joined below the session
+fn stored_path(original: &Path) -> PathBuf {
+    original
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect()
+}
+
+// Whether a stored path stays below the directory it is joined to
+fn is_inside(stored: &Path) -> bool {
+    stored.components().next().is_some()
+        && stored
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// A quarantine session: everything moved by one run, restorable as a unit
+pub struct TrashSession {
+    dir: PathBuf,
+    manifest: Manifest,
+    journal: fs::File,
+}
+
+impl TrashSession {
+    /// Creates a new session directory named after the current local time
+    pub fn create(trash_dir: &Path) -> Result<Self, TrashError> {
+        fs::create_dir_all(trash_dir).map_err(io_error(trash_dir))?;
+
+        // Claiming the directory is what picks the name, so two runs started
+        // in the same second never share a session
+        let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut session = stamp.clone();
+        let mut suffix = 1;
+        let dir = loop {
+            let dir = trash_dir.join(&session);
+            match fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    suffix += 1;
+                    session = format!("{}-{}", stamp, suffix);
+                }
+                Err(e) => return Err(io_error(&dir)(e)),
+            }
+        };
+
+        let files = dir.join(FILES_DIR);
+        fs::create_dir_all(&files).map_err(io_error(&files))?;
+
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            session,
+            created: Utc::now().to_rfc3339(),
+            entries: Vec::new(),
+        };
+        manifest.write(&dir)?;
+
+        let path = dir.join(JOURNAL_FILE);
+        let journal = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(io_error(&path))?;
+        Ok(Self {
+            dir,
+            manifest,
+            journal,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.manifest.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.manifest.entries.is_empty()
+    }
+
+    /// Moves `path` into the session, keeping its absolute layout. It is in
+    /// the journal before it is moved, nothing is moved if that fails.
+    pub fn stash(
+        &mut self,
+        path: &Path,
+        is_dir: bool,
+        size: u64,
+        tier: Option<Tier>,
+        pattern: &str,
+    ) -> Result<(), TrashError> {
+        let stored = stored_path(path);
+        let target = self.dir.join(FILES_DIR).join(&stored);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(io_error(parent))?;
+        }
+
+        let entry = TrashEntry {
+            original: path.to_path_buf(),
+            stored,
+            is_dir,
+            size,
+            tier,
+            pattern: pattern.to_string(),
+        };
+        self.journal(&JournalRecord::Intent(entry.clone()))?;
+
+        if let Err(source) = fs::rename(path, &target) {
+            let stored = entry.stored.clone();
+            if let Err(e) = self.journal(&JournalRecord::Abort { stored }) {
+                warn!("Failed to settle {} in the journal: {}", path.display(), e);
+            }
+            return Err(if source.kind() == io::ErrorKind::CrossesDevices {
+                TrashError::CrossDevice {
+                    path: path.to_path_buf(),
+                    trash: self.dir.clone(),
+                }
+            } else {
+                TrashError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            });
+        }
+
+        // The intent alone restores it too, the path is in `files` now
+        let stored = entry.stored.clone();
+        if let Err(e) = self.journal(&JournalRecord::Commit { stored }) {
+            warn!("Failed to settle {} in the journal: {}", path.display(), e);
+        }
+        self.manifest.entries.push(entry);
+        Ok(())
+    }
+
+    // Appends the record as a single write, so a crash tears at most the last line
+    fn journal(&mut self, record: &JournalRecord) -> Result<(), TrashError> {
+        let path = self.dir.join(JOURNAL_FILE);
+        let mut line = serde_json::to_vec(record).map_err(|source| TrashError::Manifest {
+            path: path.clone(),
+            source,
+        })?;
+        line.push(b'\n');
+        self.journal.write_all(&line).map_err(io_error(&path))
+    }
+
+    /// Writes the manifest and drops the journal, an empty session is removed again
+    pub fn finish(self) -> Result<Option<String>, TrashError> {
+        if self.manifest.entries.is_empty() {
+            fs::remove_dir_all(&self.dir).map_err(io_error(&self.dir))?;
+            return Ok(None);
+        }
+        self.manifest.write(&self.dir)?;
+        Ok(Some(self.manifest.session))
+    }
+}
+
+/// Every session in the trash directory, oldest first. Directories without
+/// a readable manifest, or whose manifest names another session, are skipped
+/// with a warning.
+pub fn list_sessions(trash_dir: &Path) -> Result<Vec<Manifest>, TrashError> {
+    let read_dir = match fs::read_dir(trash_dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error(trash_dir)(e)),
+    };
+
+    let mut sessions = Vec::new();
+    for entry in read_dir.flatten() {
+        if !entry.path().is_dir() {
+            continue;
+        }
+        match Manifest::read(&entry.path()) {
+            Ok(manifest) if entry.file_name() == manifest.session.as_str() => {
+                sessions.push(manifest)
+            }
+            Ok(manifest) => warn!(
+                "Skipping {}: its manifest is for session '{}'",
+                entry.path().display(),
+                manifest.session
+            ),
+            Err(e) => warn!("Skipping {}: {}", entry.path().display(), e),
+        }
+    }
+    sessions.sort_by(|a, b| a.session.cmp(&b.session));
+    Ok(sessions)
+}
+
+/// Writes the sessions that can be restored to `out`, used by `restore`
+/// without a session
+pub fn print_sessions(
+    out: &mut impl Write,
+    trash_dir: &Path,
+    sessions: &[Manifest],
+) -> io::Result<()> {
+    if sessions.is_empty() {
+        return writeln!(out, "Trash {} is empty", trash_dir.display());
+    }
+
+    writeln!(
+        out,
+        "{:<20} {:>8} {:>12} CREATED",
+        "SESSION", "ENTRIES", "BYTES"
+    )?;
+    for manifest in sessions {
+        writeln!(
+            out,
+            "{:<20} {:>8} {:>12} {}",
+            manifest.session,
+            manifest.entries.len(),
+            manifest.total_bytes(),
+            manifest.created
+        )?;
+    }
+    Ok(())
+}
+
+/// Moves every entry of a session back to its original location. Entries
+/// whose original path is taken again, or whose stored path points out of
+/// the session, stay in the trash. Returns the number
+/// of restored and kept entries.
+pub fn restore_session(trash_dir: &Path, session: &str) -> Result<(usize, usize), TrashError> {
+    // Only ever a plain name out of the trash directory, never `..` or a path
+    let mut components = Path::new(session).components();
+    let plain = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(name)), None) if name == session
+    );
+    let mut manifest = list_sessions(trash_dir)?
+        .into_iter()
+        .find(|manifest| plain && manifest.session == session)
+        .ok_or_else(|| TrashError::UnknownSession(session.to_string()))?;
+    let dir = trash_dir.join(&manifest.session);
+    let files = dir.join(FILES_DIR);
+    let mut kept = Vec::new();
+    let mut restored = 0;
+
+    for entry in std::mem::take(&mut manifest.entries) {
+        // A manifest is only a file, `..` in it must not reach out of `files`
+        if !is_inside(&entry.stored) {
+            warn!(
+                "Keeping {} in the trash, its stored path {} leaves the session",
+                entry.original.display(),
+                entry.stored.display()
+            );
+            kept.push(entry);
+            continue;
+        }
+        if fs::symlink_metadata(&entry.original).is_ok() {
+            warn!(
+                "Keeping {} in the trash, the original path exists again",
+                entry.original.display()
+            );
+            kept.push(entry);
+            continue;
+        }
+
+        let stored = files.join(&entry.stored);
+        let result = entry
+            .original
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(&stored, &entry.original));
+        match result {
+            Ok(_) => {
+                info!("Restored {}", entry.original.display());
+                restored += 1;
+            }
+            Err(e) => {
+                warn!("Failed to restore {}: {}", entry.original.display(), e);
+                kept.push(entry);
+            }
+        }
+    }
+
+    let kept_count = kept.len();
+    if kept.is_empty() {
+        fs::remove_dir_all(&dir).map_err(io_error(&dir))?;
+    } else {
+        manifest.entries = kept;
+        manifest.write(&dir)?;
+    }
+    Ok((restored, kept_count))
+}
+
+/// What a purge removed, and every session it could not remove
+#[derive(Debug, Default)]
+pub struct Purged {
+    pub sessions: usize,
+    pub bytes: u64,
+    pub errors: Vec<ScanError>,
+}
+
+/// Deletes every session created more than `older_than` ago. A session that
+/// fails to go is kept in `errors` and the others are still purged.
+pub fn purge_sessions(trash_dir: &Path, older_than: Duration) -> Result<Purged, TrashError> {
+    let now = SystemTime::now();
+    let mut purged = Purged::default();
+
+    for manifest in list_sessions(trash_dir)? {
+        let dir = trash_dir.join(&manifest.session);
+        let created = manifest
+            .created_at()
+            .or_else(|| fs::metadata(&dir).and_then(|m| m.modified()).ok());
+        let age = created.and_then(|created| now.duration_since(created).ok());
+        if age.is_none_or(|age| age < older_than) {
+            continue;
+        }
+
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            warn!("Failed to purge trash session {}: {}", manifest.session, e);
+            purged.errors.push(ScanError::from_io(&dir, &e));
+            continue;
+        }
+        info!(
+            "Purged trash session {} ({} entries)",
+            manifest.session,
+            manifest.entries.len()
+        );
+        purged.sessions += 1;
+        purged.bytes += manifest.total_bytes();
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A node_modules with one matched file, under its own root
+    fn project(root: &Path) -> PathBuf {
+        let dir = root.join("app/node_modules/pkg");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("README.md");
+        fs::write(&file, "readme").unwrap();
+        file
+    }
+
+    fn stash(session: &mut TrashSession, path: &Path) {
+        session
+            .stash(path, false, 6, Some(Tier::Safe), "README.md")
+            .unwrap();
+    }
+
+    #[test]
+    fn stash_then_restore_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = tmp.path().join("trash");
+        let file = project(tmp.path());
+
+        let mut session = TrashSession::create(&trash).unwrap();
+        stash(&mut session, &file);
+        let name = session.finish().unwrap().unwrap();
+        assert!(!file.exists());
+
+        assert_eq!(restore_session(&trash, &name).unwrap(), (1, 0));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "readme");
+        assert!(!trash.join(&name).exists());
+    }
+
+    #[test]
+    fn restore_keeps_stored_paths_that_leave_the_session() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = tmp.path().join("trash");
+        let file = project(tmp.path());
+
+        let mut session = TrashSession::create(&trash).unwrap();
+        stash(&mut session, &file);
+        session.manifest.entries[0].stored = PathBuf::from("../../outside");
+        let name = session.finish().unwrap().unwrap();
+        fs::write(trash.join("outside"), "not ours").unwrap();
+
+        assert_eq!(restore_session(&trash, &name).unwrap(), (0, 1));
+        assert!(!file.exists());
+        assert!(trash.join("outside").exists());
+        assert_eq!(list_sessions(&trash).unwrap()[0].entries.len(), 1);
+    }
+
+    #[test]
+    fn restore_keeps_entries_whose_original_exists_again() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = tmp.path().join("trash");
+        let file = project(tmp.path());
+
+        let mut session = TrashSession::create(&trash).unwrap();
+        stash(&mut session, &file);
+        let name = session.finish().unwrap().unwrap();
+        fs::write(&file, "reinstalled").unwrap();
+
+        assert_eq!(restore_session(&trash, &name).unwrap(), (0, 1));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "reinstalled");
+        assert!(
+            trash
+                .join(&name)
+                .join(FILES_DIR)
+                .join(stored_path(&file))
+                .exists()
+        );
+    }
+
+    #[test]
+    fn journal_is_replayed_after_a_crash() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = tmp.path().join("trash");
+        let file = project(tmp.path());
+        let other = file.with_file_name("LICENSE");
+        fs::write(&other, "mit").unwrap();
+
+        // Killed before `finish`, after an intent whose rename never happened
+        // and with a torn last line
+        let mut session = TrashSession::create(&trash).unwrap();
+        stash(&mut session, &file);
+        stash(&mut session, &other);
+        let mut pending = session.manifest.entries[0].clone();
+        pending.stored = PathBuf::from("never/moved");
+        session.journal(&JournalRecord::Intent(pending)).unwrap();
+        session.journal.write_all(b"{\"op\":\"int").unwrap();
+        let dir = session.dir.clone();
+        drop(session);
+
+        let manifest = Manifest::read(&dir).unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        // Replaying again after the manifest was written adds nothing
+        manifest.write(&dir).unwrap();
+        assert_eq!(Manifest::read(&dir).unwrap().entries.len(), 2);
+
+        assert_eq!(restore_session(&trash, &manifest.session).unwrap(), (2, 0));
+        assert!(file.exists() && other.exists());
+    }
+
+    #[test]
+    fn purge_only_removes_old_sessions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trash = tmp.path().join("trash");
+        let file = project(tmp.path());
+        let other = file.with_file_name("LICENSE");
+        fs::write(&other, "mit").unwrap();
+
+        let mut old = TrashSession::create(&trash).unwrap();
+        stash(&mut old, &file);
+        old.manifest.created = (Utc::now() - chrono::Duration::days(10)).to_rfc3339();
+        let old = old.finish().unwrap().unwrap();
+        let mut recent = TrashSession::create(&trash).unwrap();
+        stash(&mut recent, &other);
+        let recent = recent.finish().unwrap().unwrap();
+
+        let purged = purge_sessions(&trash, Duration::from_secs(7 * 24 * 3600)).unwrap();
+        assert_eq!((purged.sessions, purged.bytes), (1, 6));
+        assert!(purged.errors.is_empty());
+        assert!(!trash.join(old).exists());
+        assert!(trash.join(recent).exists());
+    }
+}
@@ -0,0 +1,14 @@
+//! Finds node_modules directories and the files in them that are safe to
+//! remove. `Scanner` runs a scan, the modules below hold the rest of what the
+//! `node-cleaner` binary does with the result.
+pub mod config;
+pub mod error;
+pub mod file_utils;
+pub mod observer;
+pub mod report;
+pub mod scanner;
+pub mod utils;
+
+pub use error::ScanError;
+pub use observer::ScanObserver;
+pub use scanner::{ScanResult, Scanner};
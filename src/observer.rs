@@ -0,0 +1,219 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Value, json};
+
+use crate::config::excludes::ExcludeSet;
+use crate::error::ScanError;
+use crate::file_utils::fs_utils::WalkResult;
+use crate::file_utils::matcher::PatternMatch;
+use crate::utils::read_size::TreeStats;
+
+/// Everything a scan and the removal after it report while they run. The
+/// walker calls in from many threads at once, so implementations must be
+/// cheap and thread safe. Every method does nothing by default.
+pub trait ScanObserver: Send + Sync {
+    fn on_walk_started(&self, _roots: &[PathBuf], _threads: usize) {}
+
+    /// Every directory the walker reads, from the walker threads
+    fn on_dir_entered(&self, _path: &Path) {}
+
+    /// Every outermost node_modules, as soon as the walker gets to it
+    fn on_node_modules_found(&self, _path: &Path) {}
+
+    fn on_walk_finished(&self, _walk: &WalkResult, _excludes: &ExcludeSet) {}
+
+    /// Every file a rule selected for removal
+    fn on_match(&self, _m: &PatternMatch) {}
+
+    /// Every match in one node_modules, once all of its files were checked.
+    /// Comes right after `on_size_computed` for the same node_modules.
+    fn on_node_modules_matched(&self, _path: &Path, _matches: &[PatternMatch]) {}
+
+    /// Every match of the scan, after `on_walk_finished`
+    fn on_matching_finished(&self, _matches: &[PatternMatch]) {}
+
+    fn on_size_computed(&self, _path: &Path, _tree: &TreeStats) {}
+
+    fn on_removed(&self, _path: &Path, _size: u64) {}
+
+    /// Every path that failed, they are also collected in the result
+    fn on_error(&self, _error: &ScanError) {}
+}
+
+/// Reports nothing, the default for library users
+#[derive(Debug, Default)]
+pub struct SilentObserver;
+
+impl ScanObserver for SilentObserver {}
+
+/// Hands every event to each of its observers in turn, e.g. a progress
+/// display and an `ExportObserver`
+pub struct MultiObserver {
+    observers: Vec<Arc<dyn ScanObserver>>,
+}
+
+impl MultiObserver {
+    pub fn new(observers: Vec<Arc<dyn ScanObserver>>) -> Self {
+        Self { observers }
+    }
+}
+
+impl ScanObserver for MultiObserver {
+    fn on_walk_started(&self, roots: &[PathBuf], threads: usize) {
+        for observer in &self.observers {
+            observer.on_walk_started(roots, threads);
+        }
+    }
+
+    fn on_dir_entered(&self, path: &Path) {
+        for observer in &self.observers {
+            observer.on_dir_entered(path);
+        }
+    }
+
+    fn on_node_modules_found(&self, path: &Path) {
+        for observer in &self.observers {
+            observer.on_node_modules_found(path);
+        }
+    }
+
+    fn on_walk_finished(&self, walk: &WalkResult, excludes: &ExcludeSet) {
+        for observer in &self.observers {
+            observer.on_walk_finished(walk, excludes);
+        }
+    }
+
+    fn on_match(&self, m: &PatternMatch) {
+        for observer in &self.observers {
+            observer.on_match(m);
+        }
+    }
+
+    fn on_node_modules_matched(&self, path: &Path, matches: &[PatternMatch]) {
+        for observer in &self.observers {
+            observer.on_node_modules_matched(path, matches);
+        }
+    }
+
+    fn on_matching_finished(&self, matches: &[PatternMatch]) {
+        for observer in &self.observers {
+            observer.on_matching_finished(matches);
+        }
+    }
+
+    fn on_size_computed(&self, path: &Path, tree: &TreeStats) {
+        for observer in &self.observers {
+            observer.on_size_computed(path, tree);
+        }
+    }
+
+    fn on_removed(&self, path: &Path, size: u64) {
+        for observer in &self.observers {
+            observer.on_removed(path, size);
+        }
+    }
+
+    fn on_error(&self, error: &ScanError) {
+        for observer in &self.observers {
+            observer.on_error(error);
+        }
+    }
+}
+
+/// Writes every event as one JSON object per line, tagged with a `type`
+/// field like the NDJSON export. Directories are not reported, there are
+/// far too many of them.
+pub struct JsonObserver {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonObserver {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Events on stderr, so stdout stays free for exports and reports
+    pub fn stderr() -> Self {
+        Self::new(Box::new(io::stderr()))
+    }
+
+    fn emit(&self, kind: &str, mut event: Value) {
+        event["type"] = Value::from(kind);
+        let mut out = self.out.lock().unwrap();
+        // A closed pipe only loses the progress, never the scan
+        let _ = writeln!(out, "{}", event);
+    }
+}
+
+impl ScanObserver for JsonObserver {
+    fn on_walk_started(&self, roots: &[PathBuf], threads: usize) {
+        self.emit(
+            "walk_started",
+            json!({ "roots": roots, "threads": threads }),
+        );
+    }
+
+    fn on_node_modules_found(&self, path: &Path) {
+        self.emit("node_modules_found", json!({ "path": path }));
+    }
+
+    fn on_walk_finished(&self, walk: &WalkResult, _excludes: &ExcludeSet) {
+        self.emit(
+            "walk_finished",
+            json!({
+                "elapsed_ms": walk.elapsed.as_millis() as u64,
+                "dirs": walk.dirs(),
+                "files": walk.files(),
+                "node_modules": walk.node_modules.len(),
+                "ignored": walk.ignored(),
+                "errors": walk.errors.len(),
+            }),
+        );
+    }
+
+    fn on_match(&self, m: &PatternMatch) {
+        self.emit(
+            "match",
+            json!({
+                "path": m.path,
+                "tier": m.tier.name(),
+                "pattern": m.pattern,
+                "size": m.size,
+            }),
+        );
+    }
+
+    fn on_matching_finished(&self, matches: &[PatternMatch]) {
+        let bytes: u64 = matches.iter().map(|m| m.size).sum();
+        self.emit(
+            "matching_finished",
+            json!({ "matches": matches.len(), "bytes": bytes }),
+        );
+    }
+
+    fn on_size_computed(&self, path: &Path, tree: &TreeStats) {
+        self.emit(
+            "size_computed",
+            json!({ "path": path, "bytes": tree.bytes, "files": tree.files }),
+        );
+    }
+
+    fn on_removed(&self, path: &Path, size: u64) {
+        self.emit("removed", json!({ "path": path, "size": size }));
+    }
+
+    fn on_error(&self, error: &ScanError) {
+        self.emit(
+            "error",
+            json!({
+                "kind": error.kind(),
+                "path": error.path(),
+                "message": error.to_string(),
+            }),
+        );
+    }
+}
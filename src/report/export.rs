@@ -0,0 +1,383 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config::config::Tier;
+use crate::config::excludes::ExcludeSet;
+use crate::file_utils::fs_utils::{RootSummary, WalkResult};
+use crate::file_utils::matcher::{PatternMatch, project_root};
+use crate::observer::ScanObserver;
+use crate::report::{open_output, write_csv_row};
+
+// Bumped whenever the export layout changes in a way dashboards would notice
+pub const EXPORT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ExportFormat {
+    // One JSON document
+    Json,
+    // One JSON record per line, tagged with `type`
+    Ndjson,
+    // One row per record, tagged with `record`
+    Csv,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RootStatsExport {
+    pub root: PathBuf,
+    pub dirs: usize,
+    pub files: usize,
+    pub node_modules: usize,
+    pub ignored: usize,
+    pub elapsed_ms: u128,
+}
+
+impl From<&RootSummary> for RootStatsExport {
+    fn from(summary: &RootSummary) -> Self {
+        Self {
+            root: summary.root.clone(),
+            dirs: summary.dirs,
+            files: summary.files,
+            node_modules: summary.node_modules,
+            ignored: summary.ignored,
+            elapsed_ms: summary.elapsed.as_millis(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcludeHitsExport {
+    pub rule: String,
+    pub kind: &'static str,
+    pub source: String,
+    pub hits: usize,
+}
+
+/// The counters `walk_directories` keeps, in total and per root
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanStatsExport {
+    pub elapsed_ms: u128,
+    pub dirs: usize,
+    pub files: usize,
+    pub node_modules: usize,
+    pub ignored: usize,
+    pub entries_per_sec: f64,
+    pub roots: Vec<RootStatsExport>,
+    pub excludes: Vec<ExcludeHitsExport>,
+}
+
+/// Every node_modules the walker counted, so the list adds up to
+/// `stats.node_modules`
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeModulesExport {
+    pub path: PathBuf,
+    // Inside another node_modules, sized and removed with it
+    pub nested: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchExport {
+    pub path: PathBuf,
+    pub size: u64,
+    pub tier: Tier,
+    pub pattern: String,
+    pub project: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TierTotalExport {
+    pub tier: Tier,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryExport {
+    pub matched_files: usize,
+    pub matched_bytes: u64,
+    pub tiers: Vec<TierTotalExport>,
+}
+
+/// Everything a scan found, as written by `--format`
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanExport {
+    pub version: u32,
+    pub created: String,
+    pub level: Tier,
+    pub config: String,
+    pub stats: ScanStatsExport,
+    pub node_modules: Vec<NodeModulesExport>,
+    pub matches: Vec<MatchExport>,
+    pub summary: SummaryExport,
+}
+
+// A single NDJSON line
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record<'a> {
+    Scan {
+        version: u32,
+        created: &'a str,
+        level: Tier,
+        config: &'a str,
+    },
+    Stats(&'a ScanStatsExport),
+    NodeModules(&'a NodeModulesExport),
+    Match(&'a MatchExport),
+    Summary(&'a SummaryExport),
+}
+
+// Outermost and nested node_modules together, every nested one right after
+// the node_modules holding it
+fn node_modules_export(walk: &WalkResult) -> Vec<NodeModulesExport> {
+    let outermost = walk.node_modules.iter().map(|path| (path, false));
+    let nested = walk.nested.iter().map(|path| (path, true));
+    let mut node_modules: Vec<NodeModulesExport> = outermost
+        .chain(nested)
+        .map(|(path, nested)| NodeModulesExport {
+            path: path.clone(),
+            nested,
+        })
+        .collect();
+    node_modules.sort_by(|a, b| a.path.cmp(&b.path));
+    node_modules
+}
+
+impl ScanExport {
+    pub fn new(
+        level: Tier,
+        config: String,
+        walk: &WalkResult,
+        excludes: &ExcludeSet,
+        matches: &[PatternMatch],
+    ) -> Self {
+        let mut export = Self::from_walk(level, config, walk, excludes);
+        export.set_matches(matches);
+        export
+    }
+
+    // The walk statistics, without any matches yet
+    fn from_walk(level: Tier, config: String, walk: &WalkResult, excludes: &ExcludeSet) -> Self {
+        let stats = ScanStatsExport {
+            elapsed_ms: walk.elapsed.as_millis(),
+            dirs: walk.dirs(),
+            files: walk.files(),
+            node_modules: walk.node_modules_found(),
+            ignored: walk.ignored(),
+            entries_per_sec: walk.entries_per_sec(),
+            roots: walk.roots.iter().map(RootStatsExport::from).collect(),
+            excludes: excludes
+                .rules()
+                .iter()
+                .map(|rule| ExcludeHitsExport {
+                    rule: rule.raw.clone(),
+                    kind: rule.kind_name(),
+                    source: rule.source.to_string(),
+                    hits: rule.hits(),
+                })
+                .collect(),
+        };
+
+        let mut export = Self {
+            version: EXPORT_VERSION,
+            created: Utc::now().to_rfc3339(),
+            level,
+            config,
+            stats,
+            node_modules: node_modules_export(walk),
+            matches: Vec::new(),
+            summary: SummaryExport {
+                matched_files: 0,
+                matched_bytes: 0,
+                tiers: Vec::new(),
+            },
+        };
+        export.set_matches(&[]);
+        export
+    }
+
+    // Replaces the matches and the totals over them
+    fn set_matches(&mut self, matches: &[PatternMatch]) {
+        self.matches = matches
+            .iter()
+            .map(|m| MatchExport {
+                path: m.path.clone(),
+                size: m.size,
+                tier: m.tier,
+                pattern: m.pattern.clone(),
+                project: project_root(&m.path),
+            })
+            .collect();
+
+        let tiers = self
+            .level
+            .up_to()
+            .map(|tier| {
+                let tier_matches = self.matches.iter().filter(|m| m.tier == tier);
+                TierTotalExport {
+                    tier,
+                    files: tier_matches.clone().count(),
+                    bytes: tier_matches.map(|m| m.size).sum(),
+                }
+            })
+            .collect();
+        self.summary = SummaryExport {
+            matched_files: self.matches.len(),
+            matched_bytes: self.matches.iter().map(|m| m.size).sum(),
+            tiers,
+        };
+    }
+
+    /// Writes the export to `output`, or to stdout when no file is given
+    pub fn write(&self, format: ExportFormat, output: Option<&Path>) -> io::Result<()> {
+        let mut writer = open_output(output)?;
+
+        match format {
+            ExportFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, self)?;
+                writeln!(writer)?;
+            }
+            ExportFormat::Ndjson => self.write_ndjson(&mut writer)?,
+            ExportFormat::Csv => self.write_csv(&mut writer)?,
+        }
+        writer.flush()
+    }
+
+    fn write_ndjson(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut records = vec![
+            Record::Scan {
+                version: self.version,
+                created: &self.created,
+                level: self.level,
+                config: &self.config,
+            },
+            Record::Stats(&self.stats),
+        ];
+        records.extend(self.node_modules.iter().map(Record::NodeModules));
+        records.extend(self.matches.iter().map(Record::Match));
+        records.push(Record::Summary(&self.summary));
+
+        for record in records {
+            serde_json::to_writer(&mut *writer, &record)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    // Every row has the same columns, `record` says which ones are filled:
+    // `stat` rows carry key/value (and the root in `path` for per-root
+    // counters), `node_modules` rows a path and whether it is nested, and
+    // `match` rows the rest
+    fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "record,key,value,path,size,tier,pattern,project")?;
+
+        let totals = [
+            ("elapsed_ms", self.stats.elapsed_ms.to_string()),
+            ("dirs", self.stats.dirs.to_string()),
+            ("files", self.stats.files.to_string()),
+            ("node_modules", self.stats.node_modules.to_string()),
+            ("ignored", self.stats.ignored.to_string()),
+            (
+                "entries_per_sec",
+                format!("{:.2}", self.stats.entries_per_sec),
+            ),
+            ("matched_files", self.summary.matched_files.to_string()),
+            ("matched_bytes", self.summary.matched_bytes.to_string()),
+        ];
+        for (key, value) in totals {
+            write_csv_row(writer, &["stat", key, &value, "", "", "", "", ""])?;
+        }
+
+        for root in &self.stats.roots {
+            let root_path = root.root.to_string_lossy();
+            let counters = [
+                ("dirs", root.dirs.to_string()),
+                ("files", root.files.to_string()),
+                ("node_modules", root.node_modules.to_string()),
+                ("ignored", root.ignored.to_string()),
+                ("elapsed_ms", root.elapsed_ms.to_string()),
+            ];
+            for (key, value) in counters {
+                write_csv_row(writer, &["stat", key, &value, &root_path, "", "", "", ""])?;
+            }
+        }
+
+        for tier in &self.summary.tiers {
+            let key = format!("{}_bytes", tier.tier);
+            let value = tier.bytes.to_string();
+            write_csv_row(writer, &["stat", &key, &value, "", "", "", "", ""])?;
+        }
+
+        for node_modules in &self.node_modules {
+            let path = node_modules.path.to_string_lossy();
+            let nested = node_modules.nested.to_string();
+            write_csv_row(
+                writer,
+                &["node_modules", "nested", &nested, &path, "", "", "", ""],
+            )?;
+        }
+
+        for m in &self.matches {
+            let path = m.path.to_string_lossy();
+            let size = m.size.to_string();
+            let project = m
+                .project
+                .as_deref()
+                .map(|project| project.to_string_lossy())
+                .unwrap_or_default();
+            write_csv_row(
+                writer,
+                &[
+                    "match",
+                    "",
+                    "",
+                    &path,
+                    &size,
+                    m.tier.name(),
+                    &m.pattern,
+                    &project,
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the `ScanExport` of a scan while it runs: the statistics when the
+/// walk finishes, the matches once matching is done
+pub struct ExportObserver {
+    level: Tier,
+    config: String,
+    export: Mutex<Option<ScanExport>>,
+}
+
+impl ExportObserver {
+    pub fn new(level: Tier, config: String) -> Self {
+        Self {
+            level,
+            config,
+            export: Mutex::new(None),
+        }
+    }
+
+    /// The export of the finished scan, None before the walk is done
+    pub fn take(&self) -> Option<ScanExport> {
+        self.export.lock().unwrap().take()
+    }
+}
+
+impl ScanObserver for ExportObserver {
+    fn on_walk_finished(&self, walk: &WalkResult, excludes: &ExcludeSet) {
+        let export = ScanExport::from_walk(self.level, self.config.clone(), walk, excludes);
+        *self.export.lock().unwrap() = Some(export);
+    }
+
+    fn on_matching_finished(&self, matches: &[PatternMatch]) {
+        if let Some(export) = &mut *self.export.lock().unwrap() {
+            export.set_matches(matches);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::config::config::Tier;
+use crate::report::export::ExportFormat;
+
+pub mod export;
+pub mod packages;
+pub mod projects;
+
+/// Bytes per rule tier
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TierBytes {
+    pub safe: u64,
+    pub caution: u64,
+    pub danger: u64,
+}
+
+impl TierBytes {
+    pub fn get(&self, tier: Tier) -> u64 {
+        match tier {
+            Tier::Safe => self.safe,
+            Tier::Caution => self.caution,
+            Tier::Danger => self.danger,
+        }
+    }
+
+    pub fn add(&mut self, tier: Tier, bytes: u64) {
+        match tier {
+            Tier::Safe => self.safe += bytes,
+            Tier::Caution => self.caution += bytes,
+            Tier::Danger => self.danger += bytes,
+        }
+    }
+
+    pub fn merge(&mut self, other: TierBytes) {
+        self.safe += other.safe;
+        self.caution += other.caution;
+        self.danger += other.danger;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.safe + self.caution + self.danger
+    }
+}
+
+/// A report row that can be written as a table line, JSON or CSV
+pub trait ReportRow: Serialize {
+    fn csv_header() -> Vec<&'static str>;
+    fn csv_fields(&self) -> Vec<String>;
+}
+
+/// Opens `output` for writing, or stdout when no file is given
+pub fn open_output(output: Option<&Path>) -> io::Result<BufWriter<Box<dyn Write>>> {
+    Ok(match output {
+        Some(path) => BufWriter::new(Box::new(File::create(path)?)),
+        None => BufWriter::new(Box::new(io::stdout().lock())),
+    })
+}
+
+/// Writes report rows in a machine-readable format
+pub fn write_rows<R: ReportRow>(
+    rows: &[R],
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> io::Result<()> {
+    let mut writer = open_output(output)?;
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, rows)?;
+            writeln!(writer)?;
+        }
+        ExportFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, row)?;
+                writeln!(writer)?;
+            }
+        }
+        ExportFormat::Csv => {
+            write_csv_row(&mut writer, &R::csv_header())?;
+            for row in rows {
+                let fields = row.csv_fields();
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                write_csv_row(&mut writer, &fields)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+// Quotes a CSV field when it contains a separator, quote or line break
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+pub fn write_csv_row(writer: &mut impl Write, fields: &[&str]) -> io::Result<()> {
+    let row: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+    writeln!(writer, "{}", row.join(","))
+}
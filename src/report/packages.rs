@@ -0,0 +1,231 @@
+use log::debug;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::config::Tier;
+use crate::file_utils::matcher::{PatternMatch, owning_packages, project_root};
+use crate::report::{ReportRow, TierBytes};
+use crate::utils::read_size::bytes_to_mb;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PackageSort {
+    Package,
+    Installs,
+    Projects,
+    Files,
+    Safe,
+    Caution,
+    Danger,
+    Reclaimable,
+}
+
+/// One npm package aggregated over every install on the machine
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageRow {
+    // `lodash` or `@babel/core`
+    pub package: String,
+    // Distinct copies of the package, nested installs included
+    pub installs: usize,
+    pub projects: usize,
+    pub versions: Vec<String>,
+    // Matched files inside the package
+    pub files: u64,
+    pub reclaimable: TierBytes,
+    // The pattern freeing the most bytes in this package
+    pub top_pattern: Option<String>,
+    pub top_pattern_bytes: u64,
+}
+
+impl ReportRow for PackageRow {
+    fn csv_header() -> Vec<&'static str> {
+        vec![
+            "package",
+            "installs",
+            "projects",
+            "versions",
+            "files",
+            "safe",
+            "caution",
+            "danger",
+            "reclaimable",
+            "top_pattern",
+            "top_pattern_bytes",
+        ]
+    }
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.package.clone(),
+            self.installs.to_string(),
+            self.projects.to_string(),
+            self.versions.join(" "),
+            self.files.to_string(),
+            self.reclaimable.safe.to_string(),
+            self.reclaimable.caution.to_string(),
+            self.reclaimable.danger.to_string(),
+            self.reclaimable.total().to_string(),
+            self.top_pattern.clone().unwrap_or_default(),
+            self.top_pattern_bytes.to_string(),
+        ]
+    }
+}
+
+// Per package totals while the matches are being attributed
+#[derive(Default)]
+struct PackageTotals {
+    installs: HashSet<PathBuf>,
+    projects: HashSet<PathBuf>,
+    files: u64,
+    reclaimable: TierBytes,
+    pattern_bytes: HashMap<String, u64>,
+}
+
+// `version` from the package.json of an installed package
+fn package_version(package_root: &Path) -> Option<String> {
+    let data = fs::read_to_string(package_root.join("package.json")).ok()?;
+    let manifest: serde_json::Value = serde_json::from_str(&data).ok()?;
+    manifest.get("version")?.as_str().map(str::to_string)
+}
+
+/// Attributes every match to its innermost owning package, so
+/// `node_modules/a/node_modules/@s/b/test/x.js` counts for `@s/b`, and sums
+/// each package over every install and project
+pub fn package_rows(matches: &[PatternMatch]) -> Vec<PackageRow> {
+    let mut totals: HashMap<String, PackageTotals> = HashMap::new();
+    let mut unowned = 0;
+
+    for m in matches {
+        let Some(owner) = owning_packages(&m.path).pop() else {
+            // e.g. `node_modules/.bin/tsc` or a file directly in node_modules
+            unowned += 1;
+            continue;
+        };
+
+        let package = totals.entry(owner.name).or_default();
+        package.installs.insert(owner.root);
+        if let Some(project) = project_root(&m.path) {
+            package.projects.insert(project);
+        }
+        package.files += 1;
+        package.reclaimable.add(m.tier, m.size);
+        *package.pattern_bytes.entry(m.pattern.clone()).or_insert(0) += m.size;
+    }
+
+    if unowned > 0 {
+        debug!("{} matches are not inside any package", unowned);
+    }
+
+    totals
+        .into_iter()
+        .map(|(name, package)| {
+            let versions: BTreeSet<String> = package
+                .installs
+                .iter()
+                .filter_map(|root| package_version(root))
+                .collect();
+            let top = package
+                .pattern_bytes
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)));
+
+            PackageRow {
+                package: name,
+                installs: package.installs.len(),
+                projects: package.projects.len(),
+                versions: versions.into_iter().collect(),
+                files: package.files,
+                reclaimable: package.reclaimable,
+                top_pattern_bytes: top.as_ref().map_or(0, |(_, bytes)| *bytes),
+                top_pattern: top.map(|(pattern, _)| pattern),
+            }
+        })
+        .collect()
+}
+
+/// Sorts by `sort`: names ascending, counts and sizes largest first.
+/// `reverse` flips the order.
+pub fn sort_rows(rows: &mut [PackageRow], sort: PackageSort, reverse: bool) {
+    rows.sort_by(|a, b| {
+        let ordering = match sort {
+            PackageSort::Package => a.package.cmp(&b.package),
+            PackageSort::Installs => b.installs.cmp(&a.installs),
+            PackageSort::Projects => b.projects.cmp(&a.projects),
+            PackageSort::Files => b.files.cmp(&a.files),
+            PackageSort::Safe => b.reclaimable.safe.cmp(&a.reclaimable.safe),
+            PackageSort::Caution => b.reclaimable.caution.cmp(&a.reclaimable.caution),
+            PackageSort::Danger => b.reclaimable.danger.cmp(&a.reclaimable.danger),
+            PackageSort::Reclaimable => b.reclaimable.total().cmp(&a.reclaimable.total()),
+        };
+        // Ties are broken by name so the output is stable between runs
+        let ordering = ordering.then_with(|| a.package.cmp(&b.package));
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// Writes the rows to `out` as a table, only tiers up to `level` get a column
+pub fn print_rows(
+    out: &mut impl Write,
+    rows: &[PackageRow],
+    level: Tier,
+    total_packages: usize,
+) -> io::Result<()> {
+    let tiers: Vec<Tier> = level.up_to().collect();
+
+    let mut header = format!(
+        "{:>8} {:>8} {:>8} {:>8}",
+        "INSTALLS", "PROJECTS", "VERSIONS", "FILES"
+    );
+    for tier in &tiers {
+        header.push_str(&format!(" {:>10}", tier.name().to_uppercase()));
+    }
+    header.push_str(&format!(" {:>10} {:<32} TOP PATTERN", "RECLAIM", "PACKAGE"));
+    writeln!(out, "{}", header)?;
+
+    for row in rows {
+        let mut line = format!(
+            "{:>8} {:>8} {:>8} {:>8}",
+            row.installs,
+            row.projects,
+            row.versions.len(),
+            row.files
+        );
+        for tier in &tiers {
+            line.push_str(&format!(
+                " {:>10.2}",
+                bytes_to_mb(row.reclaimable.get(*tier))
+            ));
+        }
+        let top = match &row.top_pattern {
+            Some(pattern) => format!("{} ({:.2} MB)", pattern, bytes_to_mb(row.top_pattern_bytes)),
+            None => String::from("-"),
+        };
+        line.push_str(&format!(
+            " {:>10.2} {:<32} {}",
+            bytes_to_mb(row.reclaimable.total()),
+            row.package,
+            top
+        ));
+        writeln!(out, "{}", line)?;
+    }
+
+    let reclaimable: u64 = rows.iter().map(|row| row.reclaimable.total()).sum();
+    let shown = if rows.len() < total_packages {
+        format!("{} of {} packages", rows.len(), total_packages)
+    } else {
+        format!("{} packages", rows.len())
+    };
+    writeln!(
+        out,
+        "{}: {:.2} MB reclaimable",
+        shown,
+        bytes_to_mb(reclaimable)
+    )
+}
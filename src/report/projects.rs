@@ -0,0 +1,191 @@
+use chrono::{DateTime, Local};
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::config::config::Tier;
+use crate::file_utils::matcher::{PatternMatch, project_root};
+use crate::report::{ReportRow, TierBytes};
+use crate::utils::read_size::{TreeStats, bytes_to_mb};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ProjectSort {
+    Project,
+    Size,
+    Files,
+    Safe,
+    Caution,
+    Danger,
+    Reclaimable,
+    Modified,
+}
+
+fn serialize_time<S: Serializer>(time: &Option<SystemTime>, s: S) -> Result<S::Ok, S::Error> {
+    time.map(|time| DateTime::<Local>::from(time).to_rfc3339())
+        .serialize(s)
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    time.map(|time| {
+        DateTime::<Local>::from(time)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    })
+    .unwrap_or_else(|| String::from("-"))
+}
+
+/// One project: the directory holding a node_modules tree
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectRow {
+    pub project: PathBuf,
+    pub node_modules: PathBuf,
+    // Everything inside node_modules
+    pub size: u64,
+    pub files: u64,
+    // Matched bytes per tier, what cleaning would free
+    pub reclaimable: TierBytes,
+    // Newest mtime inside node_modules
+    #[serde(serialize_with = "serialize_time")]
+    pub modified: Option<SystemTime>,
+}
+
+impl ReportRow for ProjectRow {
+    fn csv_header() -> Vec<&'static str> {
+        vec![
+            "project",
+            "node_modules",
+            "size",
+            "files",
+            "safe",
+            "caution",
+            "danger",
+            "reclaimable",
+            "modified",
+        ]
+    }
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.project.to_string_lossy().into_owned(),
+            self.node_modules.to_string_lossy().into_owned(),
+            self.size.to_string(),
+            self.files.to_string(),
+            self.reclaimable.safe.to_string(),
+            self.reclaimable.caution.to_string(),
+            self.reclaimable.danger.to_string(),
+            self.reclaimable.total().to_string(),
+            self.modified
+                .map(|time| DateTime::<Local>::from(time).to_rfc3339())
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+/// Attributes the matches to their project, next to the totals the walk
+/// tallied for every node_modules tree
+pub fn project_rows(
+    node_modules: &[PathBuf],
+    trees: &[(PathBuf, TreeStats)],
+    matches: &[PatternMatch],
+) -> Vec<ProjectRow> {
+    let mut reclaimable: HashMap<PathBuf, TierBytes> = HashMap::new();
+    for m in matches {
+        if let Some(project) = project_root(&m.path) {
+            reclaimable.entry(project).or_default().add(m.tier, m.size);
+        }
+    }
+    let trees: HashMap<&PathBuf, &TreeStats> =
+        trees.iter().map(|(path, tree)| (path, tree)).collect();
+
+    node_modules
+        .iter()
+        .map(|path| {
+            let project = path.parent().unwrap_or(path).to_path_buf();
+            let tree = trees.get(path).copied().copied().unwrap_or_default();
+            ProjectRow {
+                reclaimable: reclaimable.get(&project).copied().unwrap_or_default(),
+                project,
+                node_modules: path.clone(),
+                size: tree.bytes,
+                files: tree.files,
+                modified: tree.modified,
+            }
+        })
+        .collect()
+}
+
+/// Sorts by `sort`: names ascending, sizes and times largest/newest first.
+/// `reverse` flips the order.
+pub fn sort_rows(rows: &mut [ProjectRow], sort: ProjectSort, reverse: bool) {
+    rows.sort_by(|a, b| {
+        let ordering = match sort {
+            ProjectSort::Project => a.project.cmp(&b.project),
+            ProjectSort::Size => b.size.cmp(&a.size),
+            ProjectSort::Files => b.files.cmp(&a.files),
+            ProjectSort::Safe => b.reclaimable.safe.cmp(&a.reclaimable.safe),
+            ProjectSort::Caution => b.reclaimable.caution.cmp(&a.reclaimable.caution),
+            ProjectSort::Danger => b.reclaimable.danger.cmp(&a.reclaimable.danger),
+            ProjectSort::Reclaimable => b.reclaimable.total().cmp(&a.reclaimable.total()),
+            ProjectSort::Modified => b.modified.cmp(&a.modified),
+        };
+        // Ties are broken by path so the output is stable between runs
+        let ordering = ordering.then_with(|| a.project.cmp(&b.project));
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// Writes the rows to `out` as a table, only tiers up to `level` get a column
+pub fn print_rows(
+    out: &mut impl Write,
+    rows: &[ProjectRow],
+    level: Tier,
+    total_projects: usize,
+) -> io::Result<()> {
+    let tiers: Vec<Tier> = level.up_to().collect();
+
+    let mut header = format!("{:>10} {:>8}", "SIZE (MB)", "FILES");
+    for tier in &tiers {
+        header.push_str(&format!(" {:>10}", tier.name().to_uppercase()));
+    }
+    header.push_str(&format!(" {:>10} {:<16} PROJECT", "RECLAIM", "MODIFIED"));
+    writeln!(out, "{}", header)?;
+
+    for row in rows {
+        let mut line = format!("{:>10.2} {:>8}", bytes_to_mb(row.size), row.files);
+        for tier in &tiers {
+            line.push_str(&format!(
+                " {:>10.2}",
+                bytes_to_mb(row.reclaimable.get(*tier))
+            ));
+        }
+        line.push_str(&format!(
+            " {:>10.2} {:<16} {}",
+            bytes_to_mb(row.reclaimable.total()),
+            format_time(row.modified),
+            row.project.display()
+        ));
+        writeln!(out, "{}", line)?;
+    }
+
+    let size: u64 = rows.iter().map(|row| row.size).sum();
+    let reclaimable: u64 = rows.iter().map(|row| row.reclaimable.total()).sum();
+    let shown = if rows.len() < total_projects {
+        format!("{} of {} projects", rows.len(), total_projects)
+    } else {
+        format!("{} projects", rows.len())
+    };
+    writeln!(
+        out,
+        "{}: {:.2} MB in node_modules, {:.2} MB reclaimable",
+        shown,
+        bytes_to_mb(size),
+        bytes_to_mb(reclaimable)
+    )
+}
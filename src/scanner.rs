@@ -0,0 +1,200 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{info, warn};
+
+use crate::config::excludes::{ExcludeSet, ExcludeSource};
+use crate::error::ScanError;
+use crate::file_utils::fs_utils::{WalkOptions, WalkProgress, WalkResult, walk_directories};
+use crate::file_utils::index::{ScanIndex, fingerprint};
+use crate::file_utils::matcher::PatternMatch;
+use crate::file_utils::rules::RuleMatcher;
+use crate::observer::{ScanObserver, SilentObserver};
+
+/// Finds every node_modules below a set of roots and the files in them the
+/// rules select for removal. Nothing is removed, see `file_utils::remover`.
+///
+/// `Scanner::new(rules).root("/home/me/work").threads(4).scan()`
+#[derive(Clone)]
+pub struct Scanner {
+    roots: Vec<PathBuf>,
+    excludes: Arc<ExcludeSet>,
+    rules: Arc<RuleMatcher>,
+    options: WalkOptions,
+    observer: Arc<dyn ScanObserver>,
+    // Where the index of earlier scans lives, None to read everything
+    index: Option<PathBuf>,
+}
+
+/// Everything a scan found
+#[derive(Debug, Clone, Default)]
+pub struct ScanResult {
+    pub walk: WalkResult,
+    pub matches: Vec<PatternMatch>,
+    // Every path the scan could not read, moved here from `walk`
+    pub errors: Vec<ScanError>,
+}
+
+impl Scanner {
+    /// A scan of nothing yet, add roots with `root` or `roots`. No paths are
+    /// excluded until `excludes` is set, and nothing is reported until an
+    /// `observer` is.
+    pub fn new(rules: impl Into<Arc<RuleMatcher>>) -> Self {
+        Self {
+            roots: Vec::new(),
+            excludes: Arc::new(ExcludeSet::default()),
+            rules: rules.into(),
+            options: WalkOptions::default(),
+            observer: Arc::new(SilentObserver),
+            index: None,
+        }
+    }
+
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    pub fn roots<P: Into<PathBuf>>(mut self, roots: impl IntoIterator<Item = P>) -> Self {
+        self.roots.extend(roots.into_iter().map(Into::into));
+        self
+    }
+
+    /// Replaces the exclude rules, `ExcludeSet::defaults()` holds the ones
+    /// the CLI uses unless `--full` is given
+    pub fn excludes(mut self, excludes: impl Into<Arc<ExcludeSet>>) -> Self {
+        self.excludes = excludes.into();
+        self
+    }
+
+    /// Also skips paths matching `rule`, written like `--exclude`:
+    /// `prefix:/srv/`, `component:.git` or `glob:**/vendor/*`
+    pub fn exclude(mut self, rule: &str) -> Result<Self, String> {
+        let excludes = Arc::unwrap_or_clone(self.excludes);
+        self.excludes = Arc::new(excludes.with_rule(rule, ExcludeSource::Scanner)?);
+        Ok(self)
+    }
+
+    /// Walker threads shared between the roots, 0 uses one per core
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = Some(threads);
+        self
+    }
+
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.options.follow_links = follow;
+        self
+    }
+
+    /// Where progress, matches and errors are reported while the scan runs
+    pub fn observer(mut self, observer: Arc<dyn ScanObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Serves node_modules nothing changed in since the last scan from the
+    /// index at `path`, and updates it after every scan
+    pub fn index(mut self, path: impl Into<PathBuf>) -> Self {
+        self.index = Some(path.into());
+        self
+    }
+
+    pub fn root_paths(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    pub fn rule_matcher(&self) -> &RuleMatcher {
+        &self.rules
+    }
+
+    /// Walks the roots, sizing and matching every node_modules in the same
+    /// pass. Unreadable entries are skipped and returned as errors.
+    pub fn scan(&self) -> ScanResult {
+        self.scan_with_progress(&WalkProgress::new())
+    }
+
+    /// Same as `scan`, the walker's counters can be read from `progress`
+    /// while it runs
+    pub fn scan_with_progress(&self, progress: &WalkProgress) -> ScanResult {
+        let key = fingerprint(&self.rules, &self.excludes, self.options.follow_links);
+        let index = self
+            .index
+            .as_deref()
+            .map(|path| Arc::new(ScanIndex::load(path, key)));
+        let mut walk = walk_directories(
+            &self.roots,
+            &self.excludes,
+            Some(&self.rules),
+            index.as_ref(),
+            &self.options,
+            &*self.observer,
+            progress,
+        );
+        if let (Some(path), Some(index)) = (&self.index, index) {
+            update_index(path, index, key, &mut walk);
+        }
+        ScanResult {
+            matches: std::mem::take(&mut walk.matches),
+            errors: std::mem::take(&mut walk.errors),
+            walk,
+        }
+    }
+
+    /// Only finds the node_modules, nothing in them is read
+    pub fn walk(&self) -> WalkResult {
+        walk_directories(
+            &self.roots,
+            &self.excludes,
+            None,
+            None,
+            &self.options,
+            &*self.observer,
+            &WalkProgress::new(),
+        )
+    }
+}
+
+impl fmt::Debug for Scanner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scanner")
+            .field("roots", &self.roots)
+            .field("excludes", &self.excludes)
+            .field("rules", &self.rules)
+            .field("options", &self.options)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+// Keeps the index in step with what the scan found. A failed write only
+// costs the next scan its head start.
+fn update_index(path: &Path, index: Arc<ScanIndex>, key: u64, walk: &mut WalkResult) {
+    let reused = std::mem::take(&mut walk.reused);
+    if !reused.is_empty() {
+        info!(
+            "Served {} of {} node_modules from the scan index",
+            reused.len(),
+            walk.node_modules.len()
+        );
+    }
+
+    // The walker is done with it, so this is normally the only reference
+    let mut index = Arc::try_unwrap(index).unwrap_or_else(|index| (*index).clone());
+    let roots: Vec<PathBuf> = walk.roots.iter().map(|root| root.root.clone()).collect();
+    index.update(key, &roots, &reused, std::mem::take(&mut walk.indexed));
+    if let Err(e) = index.write(path) {
+        warn!("{}", e);
+    }
+}
+
+impl ScanResult {
+    /// Outermost node_modules, sorted
+    pub fn node_modules(&self) -> &[PathBuf] {
+        &self.walk.node_modules
+    }
+
+    pub fn reclaimable(&self) -> u64 {
+        self.matches.iter().map(|m| m.size).sum()
+    }
+}
@@ -1,16 +1,1031 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::widgets::ListState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+
+use crate::tui::event::Event;
+use crate::tui::filter::{Filters, Search, SortKey};
+use crate::tui::removal::{RemovalEvent, spawn_removal};
+use crate::tui::scan::ScanEvent;
+use crate::tui::tree::ProjectTree;
+use node_cleaner::config::config::Tier;
+use node_cleaner::error::ScanError;
+use node_cleaner::file_utils::fs_utils::{WalkProgress, WalkResult};
+use node_cleaner::file_utils::matcher::{PatternMatch, owning_packages};
+use node_cleaner::file_utils::remover::RemovalMode;
+use node_cleaner::report::TierBytes;
+use node_cleaner::utils::read_size::{TreeStats, bytes_to_mb};
+
+// Rows skipped by PageUp/PageDown
+const PAGE: usize = 10;
+
+/// A node_modules directory found by the background scan
+#[derive(Debug, Clone)]
+pub struct NodeModulesEntry {
+    pub path: PathBuf,
+    // None until the sizer got to it
+    pub tree: Option<TreeStats>,
+    // None until the matcher got to it
+    pub matches: Option<Vec<PatternMatch>>,
+    // Reclaimable bytes per package, see `package_usage`
+    pub usage: BTreeMap<String, TierBytes>,
+}
+
+// Files outside any package, like `.bin/*`
+const NO_PACKAGE: &str = "(outside packages)";
+
+// Reclaimable bytes per innermost package
+fn package_usage(matches: &[PatternMatch]) -> BTreeMap<String, TierBytes> {
+    let mut usage: BTreeMap<String, TierBytes> = BTreeMap::new();
+    for m in matches {
+        let name = match owning_packages(&m.path).pop() {
+            Some(owner) => owner.name,
+            None => NO_PACKAGE.to_string(),
+        };
+        usage.entry(name).or_default().add(m.tier, m.size);
+    }
+    usage
+}
+
+/// What the chart shows for the row under the cursor: reclaimable bytes per
+/// package for projects and directories, per pattern for packages
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub title: String,
+    // Whole node_modules size, packages are never sized on their own
+    pub size: Option<u64>,
+    pub total: TierBytes,
+    // Largest first
+    pub bars: Vec<(String, TierBytes)>,
+}
+
+impl NodeModulesEntry {
+    pub fn reclaimable(&self) -> u64 {
+        self.matches.iter().flatten().map(|m| m.size).sum()
+    }
+}
+
+/// What the list shows, Tab switches between the two
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    // One row per node_modules
+    Projects,
+    // One row per npm package, over every project
+    Packages,
+}
+
+/// How much of a row's matches are selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    None,
+    Partial,
+    All,
+}
+
+// `selected` out of `total` matches
+fn selection(selected: usize, total: usize) -> Selection {
+    match selected {
+        0 => Selection::None,
+        _ if selected == total => Selection::All,
+        _ => Selection::Partial,
+    }
+}
+
+/// One line of the list, a directory, a project or a package
+#[derive(Debug, Clone)]
+pub struct ListRow {
+    pub label: String,
+    pub depth: usize,
+    // Some(expanded) for directories that group other rows
+    pub folder: Option<bool>,
+    // Size relative to the parent directory, for the size bar
+    pub share: f64,
+    // Whole node_modules size, None for packages and unsized projects
+    pub size: Option<u64>,
+    // Still waiting for the sizer or matcher
+    pub pending: bool,
+    pub reclaimable: u64,
+    pub matches: usize,
+    pub selection: Selection,
+}
+
+/// A removal running on the worker thread
+pub struct RemovalProgress {
+    pub total: usize,
+    pub done: usize,
+    pub freed: u64,
+    pub failed: usize,
+}
+
+pub enum Mode {
+    Browse,
+    // The `/` prompt is open, with what was typed so far
+    Search(String),
+    // The confirmation popup is open
+    Confirm,
+    Removing(RemovalProgress),
+}
+
 pub struct App {
-    pub username: String,
-    #[allow(dead_code)]
-    pub sidebar_title: String,
-    pub content_title: String,
+    pub roots: Vec<PathBuf>,
+    pub level: Tier,
+    pub entries: Vec<NodeModulesEntry>,
+    index: HashMap<PathBuf, usize>,
+    // Matches grouped by their innermost package
+    packages: BTreeMap<String, Vec<PatternMatch>>,
+    // Set once the scan thread is done, with the walker's totals
+    pub finished: Option<WalkResult>,
+    // Paths the scan could not read
+    pub errors: Vec<ScanError>,
+    // The walker's live counters, for the header
+    pub progress: Arc<WalkProgress>,
+    // Where workers started from the UI report back
+    events: Sender<Event>,
+    // Ticks since start, drives the animations
+    pub ticks: usize,
+    pub view: View,
+    // Every node_modules grouped by path, rebuilt when `dirty` is set
+    tree: ProjectTree,
+    // Tree nodes shown in the projects view, in display order
+    visible: Vec<usize>,
+    collapsed: HashSet<PathBuf>,
+    // Package names shown in the packages view, in display order
+    shown_packages: Vec<String>,
+    pub filters: Filters,
+    dirty: bool,
+    pub list: ListState,
+    // Selected match paths and their size
+    selected: HashMap<PathBuf, u64>,
+    pub mode: Mode,
+    removal_mode: RemovalMode,
+    debug_mode: bool,
+    // Outcome of the last removal, shown in the status bar
+    pub message: Option<String>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(
+        roots: Vec<PathBuf>,
+        level: Tier,
+        removal_mode: RemovalMode,
+        debug_mode: bool,
+        progress: Arc<WalkProgress>,
+        events: Sender<Event>,
+    ) -> Self {
         Self {
-            username: String::from("Test"),
-            sidebar_title: String::from("sidebar_title content from app.rs goes here"),
-            content_title: String::from("content_title content from app.rs goes here"),
+            roots,
+            level,
+            entries: Vec::new(),
+            index: HashMap::new(),
+            packages: BTreeMap::new(),
+            finished: None,
+            errors: Vec::new(),
+            progress,
+            events,
+            ticks: 0,
+            view: View::Projects,
+            tree: ProjectTree::default(),
+            visible: Vec::new(),
+            collapsed: HashSet::new(),
+            shown_packages: Vec::new(),
+            filters: Filters::default(),
+            dirty: false,
+            list: ListState::default().with_selected(Some(0)),
+            selected: HashMap::new(),
+            mode: Mode::Browse,
+            removal_mode,
+            debug_mode,
+            message: None,
+        }
+    }
+
+    fn entry_mut(&mut self, path: PathBuf) -> &mut NodeModulesEntry {
+        let next = self.entries.len();
+        let position = *self.index.entry(path.clone()).or_insert(next);
+        if position == next {
+            self.entries.push(NodeModulesEntry {
+                path,
+                tree: None,
+                matches: None,
+                usage: BTreeMap::new(),
+            });
+        }
+        &mut self.entries[position]
+    }
+
+    /// Folds a message from the scan thread into the state
+    pub fn apply(&mut self, event: ScanEvent) {
+        self.dirty = true;
+        match event {
+            ScanEvent::NodeModulesFound(path) => {
+                self.entry_mut(path);
+            }
+            ScanEvent::Sized(path, tree) => self.entry_mut(path).tree = Some(tree),
+            ScanEvent::Matched(path, matches) => {
+                for m in &matches {
+                    // Files outside any package, like `.bin/*`, only show up per project
+                    if let Some(owner) = owning_packages(&m.path).pop() {
+                        self.packages.entry(owner.name).or_default().push(m.clone());
+                    }
+                }
+                let entry = self.entry_mut(path);
+                entry.usage = package_usage(&matches);
+                entry.matches = Some(matches);
+            }
+            ScanEvent::Error(e) => self.record_error(e),
+            // Its errors were already streamed one by one
+            ScanEvent::Finished(walk) => self.finished = Some(*walk),
         }
     }
+
+    // The matcher runs once per node_modules, a config error would come
+    // back every time
+    fn record_error(&mut self, e: ScanError) {
+        if !(matches!(e, ScanError::Config(_)) && self.errors.contains(&e)) {
+            self.errors.push(e);
+        }
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.tree)
+            .map(|tree| tree.bytes)
+            .sum()
+    }
+
+    pub fn reclaimable(&self) -> u64 {
+        self.entries.iter().map(NodeModulesEntry::reclaimable).sum()
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.matches.as_ref())
+            .map(Vec::len)
+            .sum()
+    }
+
+    // node_modules still waiting to be sized or matched
+    pub fn pending(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.matches.is_none())
+            .count()
+    }
+
+    pub fn selected_count(&self) -> usize {
+        self.selected.len()
+    }
+
+    pub fn selected_bytes(&self) -> u64 {
+        self.selected.values().sum()
+    }
+
+    /// Rebuilds the project tree after the entries or the selection changed.
+    /// The cursor stays on the same path, even when rows appear above it.
+    pub fn refresh(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let cursor_path = self
+            .visible
+            .get(self.cursor())
+            .map(|id| self.tree.nodes[*id].path.clone());
+        let cursor_package = self.shown_packages.get(self.cursor()).cloned();
+
+        self.tree = ProjectTree::build(&self.entries, &self.selected, &self.filters);
+        self.visible = self.tree.visible(&self.collapsed);
+        self.shown_packages = self.sorted_packages();
+
+        let row = match self.view {
+            View::Projects => cursor_path.and_then(|path| {
+                self.visible
+                    .iter()
+                    .position(|id| self.tree.nodes[*id].path == path)
+            }),
+            View::Packages => cursor_package
+                .and_then(|name| self.shown_packages.iter().position(|shown| *shown == name)),
+        };
+        if let Some(row) = row {
+            self.list.select(Some(row));
+        }
+        self.move_cursor(0);
+    }
+
+    // Whether a match counts with the current filters. The age filter goes
+    // by the node_modules the match is in.
+    fn counts(&self, m: &PatternMatch) -> bool {
+        self.filters.keeps_match(m)
+            && (self.filters.older_than.is_none()
+                || self
+                    .owning_entry(&m.path)
+                    .is_some_and(|entry| self.filters.keeps_age(&self.entries[entry])))
+    }
+
+    fn package_matches<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a PatternMatch> {
+        self.packages
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(|m| self.counts(m))
+    }
+
+    // Packages the filters keep, with something left to reclaim, in sort order
+    fn sorted_packages(&self) -> Vec<String> {
+        let mut packages: Vec<(&str, u64)> = self
+            .packages
+            .keys()
+            .filter(|name| self.filters.keeps_package(name))
+            .filter_map(|name| {
+                let mut matches = self.package_matches(name).peekable();
+                matches.peek()?;
+                Some((name.as_str(), matches.map(|m| m.size).sum()))
+            })
+            .collect();
+        packages.sort_by(|a, b| {
+            self.filters
+                .sort
+                .compare(&SortKey::package(a.0, a.1), &SortKey::package(b.0, b.1))
+        });
+        packages
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    fn row_count(&self) -> usize {
+        match self.view {
+            View::Projects => self.visible.len(),
+            View::Packages => self.shown_packages.len(),
+        }
+    }
+
+    fn row_matches(&self, row: usize) -> Vec<&PatternMatch> {
+        match self.view {
+            View::Projects => match self.visible.get(row) {
+                Some(id) => self
+                    .tree
+                    .entries_below(*id)
+                    .into_iter()
+                    .flat_map(|entry| self.entries[entry].matches.iter().flatten())
+                    .filter(|m| self.counts(m))
+                    .collect(),
+                None => Vec::new(),
+            },
+            View::Packages => match self.shown_packages.get(row) {
+                Some(name) => self.package_matches(name).collect(),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    // Every match the current view shows, what selecting everything selects
+    fn shown_matches(&self) -> Vec<&PatternMatch> {
+        match self.view {
+            View::Projects => self
+                .entries
+                .iter()
+                .filter(|entry| self.filters.keeps_entry(entry))
+                .flat_map(|entry| entry.matches.iter().flatten())
+                .filter(|m| self.counts(m))
+                .collect(),
+            View::Packages => self
+                .shown_packages
+                .iter()
+                .flat_map(|name| self.package_matches(name))
+                .collect(),
+        }
+    }
+
+    fn selection_of<'a>(&self, matches: impl IntoIterator<Item = &'a PatternMatch>) -> Selection {
+        let (mut total, mut selected) = (0, 0);
+        for m in matches {
+            total += 1;
+            if self.selected.contains_key(&m.path) {
+                selected += 1;
+            }
+        }
+        selection(selected, total)
+    }
+
+    /// The rows of the current view, in display order
+    pub fn rows(&self) -> Vec<ListRow> {
+        match self.view {
+            View::Projects => {
+                let total = self.tree.total_size();
+                self.visible
+                    .iter()
+                    .map(|id| {
+                        let node = &self.tree.nodes[*id];
+                        let parent_size = node
+                            .parent
+                            .map_or(total, |parent| self.tree.nodes[parent].totals.size);
+                        let sizing = node
+                            .entry
+                            .is_some_and(|entry| self.entries[entry].tree.is_none());
+                        ListRow {
+                            label: node.label.clone(),
+                            depth: node.depth,
+                            folder: (!node.children.is_empty())
+                                .then(|| !self.collapsed.contains(&node.path)),
+                            share: if parent_size == 0 {
+                                0.0
+                            } else {
+                                node.totals.size as f64 / parent_size as f64
+                            },
+                            size: (!sizing).then_some(node.totals.size),
+                            pending: node.totals.pending > 0,
+                            reclaimable: node.totals.reclaimable,
+                            matches: node.totals.matches,
+                            selection: selection(node.totals.selected, node.totals.matches),
+                        }
+                    })
+                    .collect()
+            }
+            View::Packages => self
+                .shown_packages
+                .iter()
+                .map(|name| ListRow {
+                    label: name.clone(),
+                    depth: 0,
+                    folder: None,
+                    share: 0.0,
+                    size: None,
+                    pending: false,
+                    reclaimable: self.package_matches(name).map(|m| m.size).sum(),
+                    matches: self.package_matches(name).count(),
+                    selection: self.selection_of(self.package_matches(name)),
+                })
+                .collect(),
+        }
+    }
+
+    /// The chart data for the row under the cursor
+    pub fn usage(&self) -> Usage {
+        let row = self.cursor();
+        let (title, size, bars) = match self.view {
+            View::Projects => {
+                let Some(id) = self.visible.get(row) else {
+                    return Usage::default();
+                };
+                let node = &self.tree.nodes[*id];
+                let mut bars: HashMap<&str, TierBytes> = HashMap::new();
+                for entry in self.tree.entries_below(*id) {
+                    for (package, bytes) in &self.entries[entry].usage {
+                        let mut kept = TierBytes::default();
+                        for tier in Tier::ALL
+                            .into_iter()
+                            .filter(|tier| self.filters.tier.is_none_or(|only| only == *tier))
+                        {
+                            kept.add(tier, bytes.get(tier));
+                        }
+                        if kept.total() > 0 {
+                            bars.entry(package).or_default().merge(kept);
+                        }
+                    }
+                }
+                let bars = bars
+                    .into_iter()
+                    .map(|(package, bytes)| (package.to_string(), bytes))
+                    .collect();
+                (
+                    node.path.to_string_lossy().into_owned(),
+                    Some(node.totals.size),
+                    bars,
+                )
+            }
+            View::Packages => {
+                let Some(name) = self.shown_packages.get(row) else {
+                    return Usage::default();
+                };
+                let mut bars: HashMap<&str, TierBytes> = HashMap::new();
+                for m in self.package_matches(name) {
+                    bars.entry(&m.pattern).or_default().add(m.tier, m.size);
+                }
+                let bars = bars
+                    .into_iter()
+                    .map(|(pattern, bytes)| (pattern.to_string(), bytes))
+                    .collect();
+                (name.clone(), None, bars)
+            }
+        };
+
+        let mut bars: Vec<(String, TierBytes)> = bars;
+        bars.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then_with(|| a.0.cmp(&b.0)));
+        let mut total = TierBytes::default();
+        for (_, bytes) in &bars {
+            total.merge(*bytes);
+        }
+        Usage {
+            title,
+            size,
+            total,
+            bars,
+        }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let last = self.row_count().saturating_sub(1);
+        let cursor = self.cursor().saturating_add_signed(delta).min(last);
+        self.list.select(Some(cursor));
+    }
+
+    // Selects every match of the row, or clears it when it was fully selected
+    fn toggle_row(&mut self) {
+        let matches: Vec<(PathBuf, u64)> = self
+            .row_matches(self.cursor())
+            .into_iter()
+            .map(|m| (m.path.clone(), m.size))
+            .collect();
+        self.toggle(matches);
+    }
+
+    // Same for every match of a tier the current view shows
+    fn toggle_tier(&mut self, tier: Tier) {
+        if tier > self.level {
+            return;
+        }
+        let matches: Vec<(PathBuf, u64)> = self
+            .shown_matches()
+            .into_iter()
+            .filter(|m| m.tier == tier)
+            .map(|m| (m.path.clone(), m.size))
+            .collect();
+        self.toggle(matches);
+    }
+
+    // Expands or collapses the directory under the cursor, when there is one
+    fn set_expanded(&mut self, expanded: bool) -> bool {
+        if self.view != View::Projects {
+            return false;
+        }
+        let Some(node) = self
+            .visible
+            .get(self.cursor())
+            .map(|id| &self.tree.nodes[*id])
+        else {
+            return false;
+        };
+        if node.children.is_empty() || self.collapsed.contains(&node.path) != expanded {
+            return false;
+        }
+
+        let path = node.path.clone();
+        if expanded {
+            self.collapsed.remove(&path);
+        } else {
+            self.collapsed.insert(path);
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn cursor_to_parent(&mut self) {
+        if self.view != View::Projects {
+            return;
+        }
+        let parent = self
+            .visible
+            .get(self.cursor())
+            .and_then(|id| self.tree.nodes[*id].parent);
+        if let Some(row) =
+            parent.and_then(|parent| self.visible.iter().position(|id| *id == parent))
+        {
+            self.list.select(Some(row));
+        }
+    }
+
+    fn expand_or_descend(&mut self) {
+        if !self.set_expanded(true) {
+            self.move_cursor(1);
+        }
+    }
+
+    fn collapse_or_ascend(&mut self) {
+        if !self.set_expanded(false) {
+            self.cursor_to_parent();
+        }
+    }
+
+    fn toggle_expanded(&mut self) {
+        if !self.set_expanded(true) {
+            self.set_expanded(false);
+        }
+    }
+
+    fn toggle(&mut self, matches: Vec<(PathBuf, u64)>) {
+        self.dirty = true;
+        let all_selected = matches
+            .iter()
+            .all(|(path, _)| self.selected.contains_key(path));
+        for (path, size) in matches {
+            if all_selected {
+                self.selected.remove(&path);
+            } else {
+                self.selected.insert(path, size);
+            }
+        }
+    }
+
+    // Selects everything the filters show
+    fn select_all(&mut self) {
+        self.dirty = true;
+        let shown: Vec<(PathBuf, u64)> = self
+            .shown_matches()
+            .into_iter()
+            .map(|m| (m.path.clone(), m.size))
+            .collect();
+        self.selected.extend(shown);
+    }
+
+    pub fn removal_prompt(&self) -> &'static str {
+        self.removal_mode.confirm_prompt()
+    }
+
+    /// The selected matches, in scan order
+    pub fn selected_matches(&self) -> Vec<&PatternMatch> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.matches.iter().flatten())
+            .filter(|m| self.selected.contains_key(&m.path))
+            .collect()
+    }
+
+    /// Handles a key press, returns true when the user wants to quit
+    pub fn on_key(&mut self, key: KeyEvent) -> bool {
+        self.refresh();
+        match self.mode {
+            Mode::Browse => return self.on_browse_key(key),
+            Mode::Search(_) => self.on_search_key(key),
+            Mode::Confirm => match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => self.start_removal(),
+                KeyCode::Char('n') | KeyCode::Esc => self.mode = Mode::Browse,
+                _ => {}
+            },
+            // Nothing to do but wait, quitting halfway would hide the outcome
+            Mode::Removing(_) => {}
+        }
+        false
+    }
+
+    fn on_search_key(&mut self, key: KeyEvent) {
+        let Mode::Search(query) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char(c) => query.push(c),
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Enter => {
+                match Search::parse(query) {
+                    Ok(search) => {
+                        self.filters.search = search;
+                        self.dirty = true;
+                    }
+                    Err(e) => self.message = Some(e.to_string()),
+                }
+                self.mode = Mode::Browse;
+            }
+            KeyCode::Esc => self.mode = Mode::Browse,
+            _ => {}
+        }
+    }
+
+    fn on_browse_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => return true,
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(1),
+            KeyCode::PageUp => self.move_cursor(-(PAGE as isize)),
+            KeyCode::PageDown => self.move_cursor(PAGE as isize),
+            KeyCode::Home | KeyCode::Char('g') => self.list.select(Some(0)),
+            KeyCode::End | KeyCode::Char('G') => self.move_cursor(isize::MAX),
+            KeyCode::Tab => {
+                self.view = match self.view {
+                    View::Projects => View::Packages,
+                    View::Packages => View::Projects,
+                };
+                self.list.select(Some(0));
+            }
+            KeyCode::Right | KeyCode::Char('l') => self.expand_or_descend(),
+            KeyCode::Left | KeyCode::Char('h') => self.collapse_or_ascend(),
+            KeyCode::Enter => self.toggle_expanded(),
+            KeyCode::Char('/') => {
+                let query = self
+                    .filters
+                    .search
+                    .as_ref()
+                    .map(|search| search.as_str().to_string());
+                self.mode = Mode::Search(query.unwrap_or_default());
+            }
+            KeyCode::Char('s') => {
+                self.filters.sort = self.filters.sort.next();
+                self.dirty = true;
+            }
+            KeyCode::Char('t') => {
+                self.filters.next_tier(self.level);
+                self.dirty = true;
+            }
+            KeyCode::Char('o') => {
+                self.filters.next_age();
+                self.dirty = true;
+            }
+            KeyCode::Char(' ') => self.toggle_row(),
+            KeyCode::Char('a') => self.select_all(),
+            KeyCode::Char('n') => {
+                self.selected.clear();
+                self.dirty = true;
+            }
+            KeyCode::Char('1') => self.toggle_tier(Tier::Safe),
+            KeyCode::Char('2') => self.toggle_tier(Tier::Caution),
+            KeyCode::Char('3') => self.toggle_tier(Tier::Danger),
+            KeyCode::Char('d') | KeyCode::Delete if !self.selected.is_empty() => {
+                self.mode = Mode::Confirm;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn start_removal(&mut self) {
+        if self.debug_mode {
+            self.message = Some(String::from("Debug mode is ON. No files were deleted."));
+            self.mode = Mode::Browse;
+            return;
+        }
+
+        let matches: Vec<PatternMatch> = self.selected_matches().into_iter().cloned().collect();
+        self.mode = Mode::Removing(RemovalProgress {
+            total: matches.len(),
+            done: 0,
+            freed: 0,
+            failed: 0,
+        });
+        spawn_removal(matches, self.removal_mode.clone(), self.events.clone());
+    }
+
+    /// Folds a message from the removal thread into the state
+    pub fn apply_removal(&mut self, event: RemovalEvent) {
+        let Mode::Removing(progress) = &mut self.mode else {
+            return;
+        };
+
+        let outcome = match event {
+            RemovalEvent::Removed(m) => {
+                progress.done += 1;
+                progress.freed += m.size;
+                self.forget(&m);
+                return;
+            }
+            RemovalEvent::Failed(e) => {
+                progress.done += 1;
+                progress.failed += 1;
+                self.errors.push(e);
+                return;
+            }
+            RemovalEvent::Aborted(e) => {
+                self.errors.push(e);
+                String::from("Could not create a trash session, nothing was removed.")
+            }
+            RemovalEvent::Finished { session, errors } => {
+                let removed = progress.done - progress.failed;
+                let trashing = matches!(self.removal_mode, RemovalMode::Trash(_));
+                let verb = if trashing { "Trashed" } else { "Removed" };
+                let mut message = format!(
+                    "{} {} files ({:.2} MB)",
+                    verb,
+                    removed,
+                    bytes_to_mb(progress.freed)
+                );
+                if progress.failed > 0 {
+                    message.push_str(&format!(", {} failed", progress.failed));
+                }
+                match session {
+                    Some(id) => {
+                        message.push_str(&format!(", undo with `node-cleaner restore {}`", id))
+                    }
+                    // Only the manifest failing leaves trashed files without a session
+                    None if trashing && removed > 0 => message.push_str(
+                        ", but the trash manifest could not be written and this may not be undoable",
+                    ),
+                    None => {}
+                }
+                self.errors.extend(errors);
+                message
+            }
+        };
+
+        self.message = Some(outcome);
+        self.mode = Mode::Browse;
+        self.move_cursor(0);
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks = self.ticks.wrapping_add(1);
+    }
+
+    // Drops a removed match from every view and from the selection
+    fn forget(&mut self, removed: &PatternMatch) {
+        self.dirty = true;
+        self.selected.remove(&removed.path);
+
+        if let Some(position) = self.owning_entry(&removed.path) {
+            let entry = &mut self.entries[position];
+            if let Some(matches) = &mut entry.matches {
+                matches.retain(|m| m.path != removed.path);
+                entry.usage = package_usage(matches);
+            }
+            if let Some(tree) = &mut entry.tree {
+                tree.bytes = tree.bytes.saturating_sub(removed.size);
+                tree.files = tree.files.saturating_sub(1);
+            }
+        }
+
+        if let Some(owner) = owning_packages(&removed.path).pop()
+            && let Some(matches) = self.packages.get_mut(&owner.name)
+        {
+            matches.retain(|m| m.path != removed.path);
+            if matches.is_empty() {
+                self.packages.remove(&owner.name);
+            }
+        }
+    }
+
+    fn owning_entry(&self, path: &Path) -> Option<usize> {
+        path.ancestors()
+            .find_map(|ancestor| self.index.get(ancestor).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::{Duration, SystemTime};
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn app(level: Tier) -> App {
+        let (events, _) = mpsc::channel();
+        App::new(
+            vec![PathBuf::from("/")],
+            level,
+            RemovalMode::Delete,
+            false,
+            Arc::new(WalkProgress::new()),
+            events,
+        )
+    }
+
+    fn pattern_match(path: &str, tier: Tier, size: u64) -> PatternMatch {
+        PatternMatch {
+            path: PathBuf::from(path),
+            tier,
+            pattern: String::from("*"),
+            size,
+        }
+    }
+
+    // Feeds a sized and matched node_modules through the scan events
+    fn found(app: &mut App, path: &str, age_days: u64, matches: Vec<PatternMatch>) {
+        let path = PathBuf::from(path);
+        let tree = TreeStats {
+            bytes: 1000,
+            files: 10,
+            modified: Some(SystemTime::now() - Duration::from_secs(age_days * DAY)),
+        };
+        app.apply(ScanEvent::Sized(path.clone(), tree));
+        app.apply(ScanEvent::Matched(path, matches));
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.on_key(KeyEvent::from(code));
+    }
+
+    fn selected_paths(app: &App) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = app.selected.keys().cloned().collect();
+        paths.sort();
+        paths
+    }
+
+    // An old and a fresh project, each with a safe and a caution match
+    fn two_projects() -> App {
+        let mut app = app(Tier::Caution);
+        found(
+            &mut app,
+            "/old/node_modules",
+            200,
+            vec![
+                pattern_match("/old/node_modules/a/README.md", Tier::Safe, 10),
+                pattern_match("/old/node_modules/a/test", Tier::Caution, 20),
+            ],
+        );
+        found(
+            &mut app,
+            "/new/node_modules",
+            1,
+            vec![
+                pattern_match("/new/node_modules/b/README.md", Tier::Safe, 30),
+                pattern_match("/new/node_modules/b/test", Tier::Caution, 40),
+            ],
+        );
+        app
+    }
+
+    #[test]
+    fn tier_toggles_select_only_what_the_filters_keep() {
+        let mut app = two_projects();
+        app.filters.older_than = Some(Duration::from_secs(30 * DAY));
+        press(&mut app, KeyCode::Char('1'));
+        assert_eq!(
+            selected_paths(&app),
+            [PathBuf::from("/old/node_modules/a/README.md")]
+        );
+        assert_eq!(app.selected_bytes(), 10);
+
+        // A second press clears them again
+        press(&mut app, KeyCode::Char('1'));
+        assert_eq!(app.selected_count(), 0);
+
+        // A tier hidden by the tier filter selects nothing
+        app.filters.tier = Some(Tier::Caution);
+        press(&mut app, KeyCode::Char('1'));
+        assert_eq!(app.selected_count(), 0);
+        press(&mut app, KeyCode::Char('2'));
+        assert_eq!(
+            selected_paths(&app),
+            [PathBuf::from("/old/node_modules/a/test")]
+        );
+    }
+
+    #[test]
+    fn tier_toggles_follow_the_search_in_the_packages_view() {
+        let mut app = two_projects();
+        app.filters.search = Search::parse("b").unwrap();
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Char('2'));
+        assert_eq!(
+            selected_paths(&app),
+            [PathBuf::from("/new/node_modules/b/test")]
+        );
+    }
+
+    #[test]
+    fn tiers_above_the_level_cannot_be_toggled() {
+        let mut app = app(Tier::Safe);
+        found(
+            &mut app,
+            "/p/node_modules",
+            1,
+            vec![pattern_match("/p/node_modules/a/.git", Tier::Danger, 5)],
+        );
+        press(&mut app, KeyCode::Char('3'));
+        assert_eq!(app.selected_count(), 0);
+    }
+
+    #[test]
+    fn removed_matches_are_forgotten() {
+        let mut app = two_projects();
+        press(&mut app, KeyCode::Char('a'));
+        assert_eq!(app.selected_count(), 4);
+
+        app.mode = Mode::Removing(RemovalProgress {
+            total: 1,
+            done: 0,
+            freed: 0,
+            failed: 0,
+        });
+        let removed = pattern_match("/old/node_modules/a/test", Tier::Caution, 20);
+        app.apply_removal(RemovalEvent::Removed(removed));
+
+        let old = &app.entries[app.index[Path::new("/old/node_modules")]];
+        assert_eq!(
+            old.tree.map(|tree| (tree.bytes, tree.files)),
+            Some((980, 9))
+        );
+        assert_eq!(old.reclaimable(), 10);
+        assert_eq!((old.usage["a"].safe, old.usage["a"].caution), (10, 0));
+        assert_eq!(app.packages["a"].len(), 1);
+        assert_eq!(app.selected_count(), 3);
+        assert_eq!(app.total_size(), 1980);
+        assert_eq!(app.reclaimable(), 80);
+        assert!(matches!(
+            app.mode,
+            Mode::Removing(RemovalProgress {
+                done: 1,
+                freed: 20,
+                ..
+            })
+        ));
+    }
 }
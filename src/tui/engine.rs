@@ -1,58 +1,254 @@
 use super::app;
+use super::event::{Event, EventLoop};
 use super::layout;
+use super::scan;
 use super::widgets;
 
+use color_eyre::config::HookBuilder;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEventKind},
+    cursor::Show,
+    event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::backend::CrosstermBackend;
 use ratatui::prelude::*;
+use ratatui::widgets::Clear;
 use std::io;
-use std::path::PathBuf;
+use std::iter;
+use std::panic;
+use std::sync::Arc;
+use std::time::Duration;
 
-#[allow(clippy::collapsible_if)]
-fn run(terminal: &mut Terminal<impl Backend>, app: &mut app::App) -> io::Result<()> {
+use node_cleaner::config::config::Tier;
+use node_cleaner::error::ScanError;
+use node_cleaner::file_utils::fs_utils::WalkProgress;
+use node_cleaner::file_utils::remover::RemovalMode;
+use node_cleaner::scanner::Scanner;
+use node_cleaner::utils::read_size::bytes_to_mb;
+
+use crate::console::{SpinnerTheme, get_ticks};
+
+// How often the screen is redrawn when nothing else happens
+const TICK_RATE: Duration = Duration::from_millis(100);
+
+fn run(
+    terminal: &mut Terminal<impl Backend>,
+    app: &mut app::App,
+    events: &EventLoop,
+) -> color_eyre::Result<()> {
+    let mut redraw = true;
     loop {
-        terminal.draw(|frame| ui(frame, app))?;
-        if let event::Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                if key.code == KeyCode::Esc {
-                    return Ok(());
+        if redraw {
+            app.refresh();
+            terminal.draw(|frame| ui(frame, app))?;
+            redraw = false;
+        }
+
+        // Wait for something to happen, then take everything already queued.
+        // Worker messages only update the state, the next tick draws them, so
+        // a burst of scan results does not cost a redraw each.
+        let first = events.next()?;
+        for event in iter::once(first).chain(events.pending()) {
+            match event {
+                Event::Key(key) => {
+                    if app.on_key(key) {
+                        return Ok(());
+                    }
+                    redraw = true;
+                }
+                Event::Resize(width, height) => {
+                    terminal.resize(Rect::new(0, 0, width, height))?;
+                    redraw = true;
                 }
+                Event::Tick => {
+                    app.tick();
+                    redraw = true;
+                }
+                Event::Scan(event) => app.apply(event),
+                Event::Removal(event) => app.apply_removal(event),
             }
         }
     }
 }
 
-fn ui(frame: &mut Frame, app: &app::App) {
-    // Temp fake data for testing
-    let paths = vec![
-        PathBuf::from("/home/user/Documents/file1.txt"),
-        PathBuf::from("/home/user/Downloads/file2.png"),
-        PathBuf::from("/etc/config.yaml"),
-        PathBuf::from("relative/path/to/file3.rs"),
+fn summary_lines(app: &app::App) -> Vec<String> {
+    let mut lines = vec![
+        format!("node_modules found: {}", app.entries.len()),
+        format!(
+            "Total size:         {:.2} MB",
+            bytes_to_mb(app.total_size())
+        ),
+        format!(
+            "Reclaimable:        {:.2} MB in {} files (tiers up to `{}`)",
+            bytes_to_mb(app.reclaimable()),
+            app.match_count(),
+            app.level
+        ),
     ];
+    // The walk counters are in the header, this is about sizing and matching
+    match &app.finished {
+        Some(_) if app.errors.is_empty() => lines.push(String::from("Scan complete")),
+        Some(_) => lines.push(format!("Scan complete, {} paths failed", app.errors.len())),
+        None => {
+            let ticks = get_ticks(SpinnerTheme::SearchScan);
+            lines.push(format!(
+                "{} Scanning... {} node_modules waiting to be sized",
+                ticks[app.ticks % ticks.len()],
+                app.pending()
+            ))
+        }
+    }
+    lines
+}
+
+fn status_line(app: &app::App) -> String {
+    // The search prompt takes over the whole bar while typing
+    if let app::Mode::Search(query) = &app.mode {
+        return format!(
+            " /{}█  (substring or glob, enter to apply, esc to cancel)",
+            query
+        );
+    }
+
+    let mut line = format!(
+        " Selected: {} files, {:.2} MB | Sort: {}",
+        app.selected_count(),
+        bytes_to_mb(app.selected_bytes()),
+        app.filters.sort.name()
+    );
+    if let Some(filters) = app.filters.describe() {
+        line.push_str(&format!(" | Filter: {}", filters));
+    }
+    if let Some(message) = &app.message {
+        line.push_str(&format!(" | {}", message));
+    }
+    line.push_str(
+        " | space toggle  a/n all/none  1-3 tier  / search  s sort  t tier  o age  tab view  d delete  esc quit",
+    );
+    line
+}
+
+fn confirm_lines(app: &app::App, prompt: &str) -> Vec<String> {
+    let selected = app.selected_matches();
+    let mut lines = vec![
+        format!(
+            "{} files, {:.2} MB selected.",
+            selected.len(),
+            bytes_to_mb(app.selected_bytes())
+        ),
+        String::new(),
+        prompt.to_string(),
+    ];
+    let danger = selected.iter().filter(|m| m.tier == Tier::Danger).count();
+    if danger > 0 {
+        lines.push(String::new());
+        lines.push(format!(
+            "{} of them matched `danger` rules and may break packages.",
+            danger
+        ));
+    }
+    lines
+}
+
+fn ui(frame: &mut Frame, app: &mut app::App) {
     // Create the layout
     let layout = layout::AppLayout::new(frame.area());
 
     // Create and render the header widget
-    let header = widgets::HeaderWidget::new(app.username.clone());
+    let roots: Vec<String> = app
+        .roots
+        .iter()
+        .map(|root| root.display().to_string())
+        .collect();
+    let header = widgets::HeaderWidget::new(roots.join(", "), app.progress.snapshot());
 
     // Get content areas
     let main_layout = layout.content_areas();
-    let main_content = widgets::ContentWidget::new(app.content_title.clone());
-    let tree_widget = widgets::PathTreeWidget::new(paths);
+    let main_content = widgets::ContentWidget::new(summary_lines(app));
+    let rows = app.rows();
+    let tree_widget = widgets::PathTreeWidget::new(&rows, app.view);
+    let status = widgets::StatusWidget::new(status_line(app));
 
     frame.render_widget(widgets::HeaderWidget::widget(&header), layout.header);
 
-    frame.render_widget(widgets::ContentWidget::widget(&main_content), main_layout.1);
+    let (summary_area, chart_area) = layout.main_areas(main_layout.1);
+    frame.render_widget(widgets::ContentWidget::widget(&main_content), summary_area);
+
+    let usage = app.usage();
+    let chart = widgets::UsageChartWidget::new(&usage, chart_area.width.saturating_sub(2) as usize);
+    frame.render_widget(widgets::UsageChartWidget::widget(&chart), chart_area);
+
+    frame.render_stateful_widget(
+        widgets::PathTreeWidget::widget(&tree_widget),
+        main_layout.0,
+        &mut app.list,
+    );
 
-    frame.render_widget(widgets::PathTreeWidget::widget(&tree_widget), main_layout.0);
+    frame.render_widget(widgets::StatusWidget::widget(&status), layout.status);
+
+    match &app.mode {
+        app::Mode::Browse | app::Mode::Search(_) => {}
+        app::Mode::Confirm => {
+            let area = layout.popup(60, 9);
+            let popup = widgets::ConfirmPopup::new(confirm_lines(app, app.removal_prompt()));
+            frame.render_widget(Clear, area);
+            frame.render_widget(widgets::ConfirmPopup::widget(&popup), area);
+        }
+        app::Mode::Removing(progress) => {
+            let area = layout.popup(60, 3);
+            let popup = widgets::ProgressPopup::new(
+                progress.done,
+                progress.total,
+                progress.freed,
+                progress.failed,
+            );
+            frame.render_widget(Clear, area);
+            frame.render_widget(widgets::ProgressPopup::widget(&popup), area);
+        }
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        Show
+    )
 }
 
-pub fn run_tui() -> io::Result<()> {
+// Puts the terminal back before color-eyre prints a panic or error report,
+// otherwise the report lands on the alternate screen and is gone on exit
+fn install_hooks() -> color_eyre::Result<()> {
+    let (panic_hook, eyre_hook) = HookBuilder::default().into_hooks();
+
+    let panic_hook = panic_hook.into_panic_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        panic_hook(info);
+    }));
+
+    let eyre_hook = eyre_hook.into_eyre_hook();
+    color_eyre::eyre::set_hook(Box::new(move |error| {
+        let _ = restore_terminal();
+        eyre_hook(error)
+    }))?;
+    Ok(())
+}
+
+pub fn run_tui(
+    scanner: Scanner,
+    removal_mode: RemovalMode,
+    debug_mode: bool,
+) -> color_eyre::Result<Vec<ScanError>> {
+    install_hooks()?;
+
+    // Log lines would draw over the alternate screen
+    let log_level = log::max_level();
+    log::set_max_level(log::LevelFilter::Off);
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -60,24 +256,26 @@ pub fn run_tui() -> io::Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app state
-    let mut app = app::App::new();
+    // Create app state and start scanning in the background
+    let events = EventLoop::new(TICK_RATE);
+    let progress = Arc::new(WalkProgress::new());
+    let mut app = app::App::new(
+        scanner.root_paths().to_vec(),
+        scanner.rule_matcher().level(),
+        removal_mode,
+        debug_mode,
+        Arc::clone(&progress),
+        events.sender(),
+    );
+    // Spinners would draw over the alternate screen too
+    scan::spawn_scan(scanner, progress, events.sender());
 
     // Run the app
-    let res = run(&mut terminal, &mut app);
+    let res = run(&mut terminal, &mut app, &events);
+    drop(events);
 
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-
-    if let Err(err) = res {
-        println!("{:?}", err)
-    }
-
-    Ok(())
+    restore_terminal()?;
+    log::set_max_level(log_level);
+    // Handed back so the CLI can sum them up once the terminal is restored
+    res.map(|()| app.errors)
 }
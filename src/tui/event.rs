@@ -0,0 +1,97 @@
+use crossterm::event::{self, Event as TermEvent, KeyEvent, KeyEventKind};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::tui::removal::RemovalEvent;
+use crate::tui::scan::ScanEvent;
+
+/// Everything the TUI reacts to, from the terminal and from the workers
+#[derive(Debug)]
+pub enum Event {
+    Key(KeyEvent),
+    Resize(u16, u16),
+    // Redraw time, even when nothing else happened
+    Tick,
+    Scan(ScanEvent),
+    Removal(RemovalEvent),
+}
+
+/// Merges terminal input, a periodic tick and the workers' messages into one
+/// channel. Workers get a sender through `sender()`.
+pub struct EventLoop {
+    events: Receiver<Event>,
+    sender: Sender<Event>,
+    stop: Arc<AtomicBool>,
+}
+
+impl EventLoop {
+    pub fn new(tick_rate: Duration) -> Self {
+        let (sender, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let input = sender.clone();
+        let input_stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let mut last_tick = Instant::now();
+            while !input_stop.load(Ordering::Relaxed) {
+                // Wait for input at most until the next tick is due
+                let timeout = tick_rate.saturating_sub(last_tick.elapsed());
+                let event = match event::poll(timeout) {
+                    Ok(true) => match event::read() {
+                        Ok(TermEvent::Key(key)) if key.kind == KeyEventKind::Press => {
+                            Some(Event::Key(key))
+                        }
+                        Ok(TermEvent::Resize(width, height)) => Some(Event::Resize(width, height)),
+                        Ok(_) => None,
+                        Err(_) => return,
+                    },
+                    Ok(false) => None,
+                    Err(_) => return,
+                };
+                if let Some(event) = event
+                    && input.send(event).is_err()
+                {
+                    return;
+                }
+
+                if last_tick.elapsed() >= tick_rate {
+                    if input.send(Event::Tick).is_err() {
+                        return;
+                    }
+                    last_tick = Instant::now();
+                }
+            }
+        });
+
+        Self {
+            events,
+            sender,
+            stop,
+        }
+    }
+
+    pub fn sender(&self) -> Sender<Event> {
+        self.sender.clone()
+    }
+
+    /// Waits for the next event
+    pub fn next(&self) -> Result<Event, RecvError> {
+        self.events.recv()
+    }
+
+    /// Events that are already queued, without waiting
+    pub fn pending(&self) -> impl Iterator<Item = Event> + '_ {
+        self.events.try_iter()
+    }
+}
+
+impl Drop for EventLoop {
+    // Stops reading the terminal, so the input thread does not steal keys
+    // from whatever runs after the TUI
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,337 @@
+use std::cmp::Ordering;
+use std::time::{Duration, SystemTime};
+
+use crate::tui::app::NodeModulesEntry;
+use node_cleaner::config::config::Tier;
+use node_cleaner::file_utils::matcher::PatternMatch;
+use node_cleaner::utils::age::format_age;
+use node_cleaner::utils::glob::{Glob, GlobError, has_glob_meta};
+
+const DAY: u64 = 24 * 60 * 60;
+
+// Thresholds the age filter cycles through, None shows everything
+const AGE_FILTERS: [Option<Duration>; 5] = [
+    None,
+    Some(Duration::from_secs(30 * DAY)),
+    Some(Duration::from_secs(90 * DAY)),
+    Some(Duration::from_secs(180 * DAY)),
+    Some(Duration::from_secs(365 * DAY)),
+];
+
+/// Order of the rows, cycled with `s`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortMode {
+    // Largest first
+    #[default]
+    Size,
+    // Least recently changed first
+    Age,
+    Name,
+    // Most reclaimable bytes first
+    Reclaimable,
+}
+
+impl SortMode {
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::Size => SortMode::Age,
+            SortMode::Age => SortMode::Name,
+            SortMode::Name => SortMode::Reclaimable,
+            SortMode::Reclaimable => SortMode::Size,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortMode::Size => "size",
+            SortMode::Age => "age",
+            SortMode::Name => "name",
+            SortMode::Reclaimable => "reclaimable",
+        }
+    }
+
+    /// Compares two rows by this mode, ties are broken by name
+    pub fn compare(self, a: &SortKey, b: &SortKey) -> Ordering {
+        let ordering = match self {
+            SortMode::Size => b.size.cmp(&a.size),
+            // Rows that are not sized yet have no age, they go last
+            SortMode::Age => match (a.modified, b.modified) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            SortMode::Name => Ordering::Equal,
+            SortMode::Reclaimable => b.reclaimable.cmp(&a.reclaimable),
+        };
+        ordering.then_with(|| a.name.cmp(b.name))
+    }
+}
+
+/// What a row is sorted by. Packages have no size or age of their own, they
+/// use their reclaimable bytes and no age.
+#[derive(Debug, Clone, Copy)]
+pub struct SortKey<'a> {
+    pub name: &'a str,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub reclaimable: u64,
+}
+
+impl<'a> SortKey<'a> {
+    pub fn package(name: &'a str, reclaimable: u64) -> Self {
+        Self {
+            name,
+            size: reclaimable,
+            modified: None,
+            reclaimable,
+        }
+    }
+}
+
+/// What `/` searches for: a case-insensitive substring, or a glob when the
+/// text has `*`, `?` or `[` in it
+#[derive(Debug, Clone)]
+pub enum Search {
+    Substring(String),
+    Glob(Glob),
+}
+
+impl Search {
+    /// None for an empty search, which clears the filter
+    pub fn parse(raw: &str) -> Result<Option<Self>, GlobError> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return Ok(None);
+        }
+        if has_glob_meta(raw) {
+            return Glob::new_case_insensitive(raw).map(|glob| Some(Search::Glob(glob)));
+        }
+        Ok(Some(Search::Substring(raw.to_lowercase())))
+    }
+
+    // Globs match any run of whole path segments, so `work/*` finds
+    // `/home/me/work/app/node_modules`
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        match self {
+            Search::Substring(needle) => text.contains(needle.as_str()),
+            Search::Glob(glob) => {
+                let segments: Vec<&str> = text.split('/').filter(|s| !s.is_empty()).collect();
+                (0..segments.len()).any(|start| glob.match_prefix_len(&segments[start..]).is_some())
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Search::Substring(needle) => needle,
+            Search::Glob(glob) => glob.as_str(),
+        }
+    }
+}
+
+/// Which rows and matches the list shows, and in what order
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    pub search: Option<Search>,
+    // Only matches of this tier count
+    pub tier: Option<Tier>,
+    // Only node_modules unchanged for at least this long
+    pub older_than: Option<Duration>,
+    pub sort: SortMode,
+}
+
+impl Filters {
+    /// Cycles through every tier up to `level`, then back to all tiers
+    pub fn next_tier(&mut self, level: Tier) {
+        self.tier = match self.tier {
+            None => Some(Tier::Safe),
+            Some(tier) => level.up_to().find(|next| *next > tier),
+        };
+    }
+
+    pub fn next_age(&mut self) {
+        let position = AGE_FILTERS
+            .iter()
+            .position(|age| *age == self.older_than)
+            .unwrap_or(0);
+        self.older_than = AGE_FILTERS[(position + 1) % AGE_FILTERS.len()];
+    }
+
+    pub fn keeps_match(&self, m: &PatternMatch) -> bool {
+        self.tier.is_none_or(|tier| m.tier == tier)
+    }
+
+    // A node_modules still being sized has no age yet, it only shows up
+    // once it is known to be old enough
+    pub fn keeps_age(&self, entry: &NodeModulesEntry) -> bool {
+        let Some(older_than) = self.older_than else {
+            return true;
+        };
+        entry
+            .tree
+            .and_then(|tree| tree.modified)
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= older_than)
+    }
+
+    /// Whether a node_modules shows up in the projects view
+    pub fn keeps_entry(&self, entry: &NodeModulesEntry) -> bool {
+        if !self.keeps_age(entry) {
+            return false;
+        }
+        if let Some(search) = &self.search
+            && !search.matches(&entry.path.to_string_lossy())
+        {
+            return false;
+        }
+        // With a tier filter, only projects with something of that tier are left
+        self.tier.is_none() || entry.matches.iter().flatten().any(|m| self.keeps_match(m))
+    }
+
+    pub fn keeps_package(&self, name: &str) -> bool {
+        self.search
+            .as_ref()
+            .is_none_or(|search| search.matches(name))
+    }
+
+    /// The active filters for the status bar, None when nothing is filtered
+    pub fn describe(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(search) = &self.search {
+            parts.push(format!("/{}", search.as_str()));
+        }
+        if let Some(tier) = self.tier {
+            parts.push(format!("tier {}", tier));
+        }
+        if let Some(older_than) = self.older_than {
+            parts.push(format!("older than {}", format_age(older_than)));
+        }
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node_cleaner::utils::read_size::TreeStats;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn days_ago(days: u64) -> SystemTime {
+        SystemTime::now() - Duration::from_secs(days * DAY)
+    }
+
+    fn search(raw: &str) -> Search {
+        Search::parse(raw).unwrap().unwrap()
+    }
+
+    // A sized and matched node_modules with one match per tier
+    fn entry(path: &str, modified: SystemTime, tiers: &[Tier]) -> NodeModulesEntry {
+        let matches = tiers
+            .iter()
+            .enumerate()
+            .map(|(i, tier)| PatternMatch {
+                path: PathBuf::from(format!("{}/pkg/file{}", path, i)),
+                tier: *tier,
+                pattern: String::from("file*"),
+                size: 10,
+            })
+            .collect();
+        NodeModulesEntry {
+            path: PathBuf::from(path),
+            tree: Some(TreeStats {
+                bytes: 100,
+                files: 10,
+                modified: Some(modified),
+            }),
+            matches: Some(matches),
+            usage: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn tier_filter_cycles_up_to_the_level() {
+        let mut filters = Filters::default();
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            filters.next_tier(Tier::Caution);
+            seen.push(filters.tier);
+        }
+        assert_eq!(
+            seen,
+            [
+                Some(Tier::Safe),
+                Some(Tier::Caution),
+                None,
+                Some(Tier::Safe)
+            ]
+        );
+
+        filters.tier = Some(Tier::Caution);
+        filters.next_tier(Tier::Danger);
+        assert_eq!(filters.tier, Some(Tier::Danger));
+        filters.next_tier(Tier::Danger);
+        assert_eq!(filters.tier, None);
+    }
+
+    #[test]
+    fn age_filter_cycles_back_to_everything() {
+        let mut filters = Filters::default();
+        let mut seen = Vec::new();
+        for _ in 0..AGE_FILTERS.len() {
+            filters.next_age();
+            seen.push(filters.older_than);
+        }
+        assert_eq!(seen[..4], AGE_FILTERS[1..]);
+        assert_eq!(filters.older_than, None);
+    }
+
+    #[test]
+    fn searches_are_substrings_unless_they_have_glob_characters() {
+        assert!(Search::parse("  ").unwrap().is_none());
+        assert!(Search::parse("[abc").is_err());
+
+        let substring = search(" Work/App ");
+        assert!(matches!(substring, Search::Substring(_)));
+        assert!(substring.matches("/home/me/WORK/app/node_modules"));
+        assert!(substring.matches("/home/me/homework/apps"));
+        assert!(!substring.matches("/home/me/work/lib"));
+
+        // Globs only match whole segments
+        let glob = search("work/*");
+        assert!(matches!(glob, Search::Glob(_)));
+        assert!(glob.matches("/home/me/Work/app/node_modules"));
+        assert!(!glob.matches("/home/me/homework/app"));
+        assert_eq!(glob.as_str(), "work/*");
+
+        assert!(search("react*").matches("react-dom"));
+        assert!(!search("react*").matches("preact"));
+    }
+
+    #[test]
+    fn entries_must_pass_the_tier_and_age_filters_together() {
+        let old_safe = entry("/a/node_modules", days_ago(100), &[Tier::Safe]);
+        let old_caution = entry("/b/node_modules", days_ago(100), &[Tier::Caution]);
+        let new_safe = entry("/c/node_modules", days_ago(1), &[Tier::Safe]);
+        let mut not_sized = entry("/d/node_modules", days_ago(100), &[Tier::Safe]);
+        not_sized.tree = None;
+
+        let filters = Filters {
+            tier: Some(Tier::Safe),
+            older_than: Some(Duration::from_secs(90 * DAY)),
+            ..Filters::default()
+        };
+        assert!(filters.keeps_entry(&old_safe));
+        assert!(!filters.keeps_entry(&old_caution));
+        assert!(!filters.keeps_entry(&new_safe));
+        // No age yet, it only shows up once sized
+        assert!(!filters.keeps_entry(&not_sized));
+
+        let everything = Filters::default();
+        for entry in [&old_safe, &old_caution, &new_safe, &not_sized] {
+            assert!(everything.keeps_entry(entry));
+        }
+    }
+}
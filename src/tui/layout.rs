@@ -5,7 +5,7 @@ use ratatui::layout::{Constraint, Direction, Layout, Rect};
 pub struct AppLayout {
     pub header: Rect,
     pub content: Rect,
-    pub _status: Rect,
+    pub status: Rect,
 }
 
 impl AppLayout {
@@ -23,7 +23,7 @@ impl AppLayout {
         Self {
             header: vertical[0],
             content: vertical[1],
-            _status: vertical[2],
+            status: vertical[2],
         }
     }
 
@@ -36,4 +36,30 @@ impl AppLayout {
 
         (horizontal[0], horizontal[1])
     }
+
+    /// Splits the main pane into the scan summary and the usage chart below it
+    pub fn main_areas(&self, main: Rect) -> (Rect, Rect) {
+        let vertical = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(6), // Summary lines plus borders
+                Constraint::Min(0),    // Usage chart
+            ])
+            .split(main);
+
+        (vertical[0], vertical[1])
+    }
+
+    /// A `width` x `height` box in the middle of the screen, for popups
+    pub fn popup(&self, width: u16, height: u16) -> Rect {
+        let area = self.content;
+        let width = width.min(area.width);
+        let height = height.min(area.height);
+        Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        }
+    }
 }
@@ -1,4 +1,9 @@
 pub mod app;
 pub mod engine;
+pub mod event;
+pub mod filter;
 pub mod layout;
+pub mod removal;
+pub mod scan;
+pub mod tree;
 pub mod widgets;
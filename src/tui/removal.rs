@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use crate::tui::event::Event;
+use node_cleaner::error::ScanError;
+use node_cleaner::file_utils::matcher::PatternMatch;
+use node_cleaner::file_utils::remover::{RemovalMode, Remover};
+use node_cleaner::observer::SilentObserver;
+
+/// Progress of a removal started from the TUI, one event per path
+#[derive(Debug)]
+pub enum RemovalEvent {
+    Removed(PatternMatch),
+    Failed(ScanError),
+    // The trash session could not be created, nothing was touched
+    Aborted(ScanError),
+    // Trash session id when the paths were moved to the trash, and what
+    // failed after the last path, such as writing the trash manifest
+    Finished {
+        session: Option<String>,
+        errors: Vec<ScanError>,
+    },
+}
+
+/// Removes `matches` on a background thread through the same `Remover` the
+/// CLI uses, so `--trash` sessions work the same from the TUI
+pub fn spawn_removal(matches: Vec<PatternMatch>, mode: RemovalMode, events: Sender<Event>) {
+    let send = move |event| events.send(Event::Removal(event));
+    thread::spawn(move || {
+        // Every path is reported through `events` below instead
+        let mut remover = match Remover::start(&mode, Arc::new(SilentObserver)) {
+            Ok(remover) => remover,
+            Err(e) => {
+                let _ = send(RemovalEvent::Aborted(e));
+                return;
+            }
+        };
+
+        let mut failed = 0;
+        for m in matches {
+            let event = match remover.remove(&m.path, false, m.size, Some(m.tier), &m.pattern) {
+                Ok(()) => RemovalEvent::Removed(m),
+                Err(e) => {
+                    failed += 1;
+                    RemovalEvent::Failed(e)
+                }
+            };
+            // Keep going even if the UI is gone, a half done removal helps nobody
+            let _ = send(event);
+        }
+
+        // The first `failed` errors were already sent with their paths
+        let (session, errors) = remover.finish();
+        let errors = errors.into_iter().skip(failed).collect();
+        let _ = send(RemovalEvent::Finished { session, errors });
+    });
+}
@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use crate::tui::event::Event;
+use node_cleaner::error::ScanError;
+use node_cleaner::file_utils::fs_utils::{WalkProgress, WalkResult};
+use node_cleaner::file_utils::matcher::PatternMatch;
+use node_cleaner::observer::ScanObserver;
+use node_cleaner::scanner::Scanner;
+use node_cleaner::utils::read_size::TreeStats;
+
+/// Progress of the background scan, in the order it happens for a single
+/// node_modules: found, then sized, then matched
+#[derive(Debug)]
+pub enum ScanEvent {
+    NodeModulesFound(PathBuf),
+    Sized(PathBuf, TreeStats),
+    Matched(PathBuf, Vec<PatternMatch>),
+    // A path the walker or the matcher could not read
+    Error(ScanError),
+    Finished(Box<WalkResult>),
+}
+
+// Turns the scanner's reports into events for the UI
+struct TuiObserver {
+    events: Sender<Event>,
+}
+
+impl TuiObserver {
+    // The UI hanging up only stops the streaming, the scan finishes on its own
+    fn send(&self, event: ScanEvent) {
+        let _ = self.events.send(Event::Scan(event));
+    }
+}
+
+impl ScanObserver for TuiObserver {
+    fn on_node_modules_found(&self, path: &Path) {
+        self.send(ScanEvent::NodeModulesFound(path.to_path_buf()));
+    }
+
+    fn on_size_computed(&self, path: &Path, tree: &TreeStats) {
+        self.send(ScanEvent::Sized(path.to_path_buf(), *tree));
+    }
+
+    fn on_node_modules_matched(&self, path: &Path, matches: &[PatternMatch]) {
+        self.send(ScanEvent::Matched(path.to_path_buf(), matches.to_vec()));
+    }
+
+    fn on_error(&self, error: &ScanError) {
+        self.send(ScanEvent::Error(error.clone()));
+    }
+}
+
+/// Runs the scan on a background thread and streams its progress to
+/// `events`. Every node_modules is sent as soon as the walker finds it and
+/// again once the walker is done sizing and matching it, the walker's
+/// counters can be read from `progress` at any time.
+pub fn spawn_scan(scanner: Scanner, progress: Arc<WalkProgress>, events: Sender<Event>) {
+    thread::spawn(move || {
+        let observer = TuiObserver {
+            events: events.clone(),
+        };
+        let result = scanner
+            .observer(Arc::new(observer))
+            .scan_with_progress(&progress);
+        let _ = events.send(Event::Scan(ScanEvent::Finished(Box::new(result.walk))));
+    });
+}
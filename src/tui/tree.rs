@@ -0,0 +1,237 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::tui::app::NodeModulesEntry;
+use crate::tui::filter::{Filters, SortKey};
+
+/// A directory in the project tree. Chains of directories with a single
+/// child are merged into one node, so `/home/me/work` is a single line.
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    pub path: PathBuf,
+    // Path relative to the parent node
+    pub label: String,
+    pub depth: usize,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    // Index into `App::entries` when this node is a node_modules
+    pub entry: Option<usize>,
+    // Over every node_modules below
+    pub totals: Totals,
+}
+
+/// What a node adds up, only counting matches the filters keep
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Totals {
+    pub size: u64,
+    // Newest change, see `TreeStats::modified`
+    pub modified: Option<SystemTime>,
+    pub reclaimable: u64,
+    pub matches: usize,
+    pub selected: usize,
+    // node_modules still waiting for the sizer or matcher
+    pub pending: usize,
+}
+
+impl Totals {
+    fn of_entry(
+        entry: &NodeModulesEntry,
+        selected: &HashMap<PathBuf, u64>,
+        filters: &Filters,
+    ) -> Self {
+        let matches: Vec<_> = entry
+            .matches
+            .iter()
+            .flatten()
+            .filter(|m| filters.keeps_match(m))
+            .collect();
+        Self {
+            size: entry.tree.map_or(0, |tree| tree.bytes),
+            modified: entry.tree.and_then(|tree| tree.modified),
+            reclaimable: matches.iter().map(|m| m.size).sum(),
+            matches: matches.len(),
+            // Nothing to look up while the selection is empty, which is most of the scan
+            selected: if selected.is_empty() {
+                0
+            } else {
+                matches
+                    .iter()
+                    .filter(|m| selected.contains_key(&m.path))
+                    .count()
+            },
+            pending: usize::from(entry.tree.is_none() || entry.matches.is_none()),
+        }
+    }
+
+    fn add(&mut self, other: &Totals) {
+        self.size += other.size;
+        self.modified = self.modified.max(other.modified);
+        self.reclaimable += other.reclaimable;
+        self.matches += other.matches;
+        self.selected += other.selected;
+        self.pending += other.pending;
+    }
+}
+
+impl TreeNode {
+    fn sort_key(&self) -> SortKey<'_> {
+        SortKey {
+            name: &self.label,
+            size: self.totals.size,
+            modified: self.totals.modified,
+            reclaimable: self.totals.reclaimable,
+        }
+    }
+}
+
+// Directory components while the tree is being built
+#[derive(Default)]
+struct TrieNode {
+    children: BTreeMap<OsString, usize>,
+    // The node_modules ending here, with its totals
+    entry: Option<(usize, Totals)>,
+}
+
+/// Every node_modules the filters keep, grouped by common path prefix
+#[derive(Debug, Default)]
+pub struct ProjectTree {
+    pub nodes: Vec<TreeNode>,
+    roots: Vec<usize>,
+}
+
+impl ProjectTree {
+    /// Builds the tree and its totals, `selected` holds the selected match
+    /// paths. Only matches the filters keep are counted.
+    pub fn build(
+        entries: &[NodeModulesEntry],
+        selected: &HashMap<PathBuf, u64>,
+        filters: &Filters,
+    ) -> Self {
+        let mut trie = vec![TrieNode::default()];
+        for (index, entry) in entries.iter().enumerate() {
+            if !filters.keeps_entry(entry) {
+                continue;
+            }
+            let mut at = 0;
+            for component in entry.path.components() {
+                let name = component.as_os_str().to_os_string();
+                at = match trie[at].children.get(&name) {
+                    Some(child) => *child,
+                    None => {
+                        trie.push(TrieNode::default());
+                        let child = trie.len() - 1;
+                        trie[at].children.insert(name, child);
+                        child
+                    }
+                };
+            }
+            trie[at].entry = Some((index, Totals::of_entry(entry, selected, filters)));
+        }
+
+        let mut tree = ProjectTree::default();
+        for (name, child) in &trie[0].children {
+            let root = tree.emit(&trie, *child, PathBuf::from(name), None);
+            tree.roots.push(root);
+        }
+        tree.sort(filters);
+        tree
+    }
+
+    // Orders the roots and every node's children by the sort mode
+    fn sort(&mut self, filters: &Filters) {
+        let compare = |nodes: &[TreeNode], a: usize, b: usize| {
+            filters
+                .sort
+                .compare(&nodes[a].sort_key(), &nodes[b].sort_key())
+        };
+
+        let mut roots = std::mem::take(&mut self.roots);
+        roots.sort_by(|a, b| compare(&self.nodes, *a, *b));
+        self.roots = roots;
+        for id in 0..self.nodes.len() {
+            let mut children = std::mem::take(&mut self.nodes[id].children);
+            children.sort_by(|a, b| compare(&self.nodes, *a, *b));
+            self.nodes[id].children = children;
+        }
+    }
+
+    // Turns a trie node into a tree node, merging single child chains, and
+    // sums the totals of everything below it
+    fn emit(
+        &mut self,
+        trie: &[TrieNode],
+        mut at: usize,
+        mut path: PathBuf,
+        parent: Option<usize>,
+    ) -> usize {
+        while trie[at].entry.is_none() && trie[at].children.len() == 1 {
+            let (name, child) = trie[at].children.iter().next().unwrap();
+            path.push(name);
+            at = *child;
+        }
+
+        let (label, depth) = match parent {
+            Some(parent) => {
+                let parent = &self.nodes[parent];
+                let label = path.strip_prefix(&parent.path).unwrap_or(&path);
+                (label.to_string_lossy().into_owned(), parent.depth + 1)
+            }
+            None => (path.to_string_lossy().into_owned(), 0),
+        };
+
+        let id = self.nodes.len();
+        self.nodes.push(TreeNode {
+            path: path.clone(),
+            label,
+            depth,
+            parent,
+            children: Vec::new(),
+            entry: trie[at].entry.map(|(index, _)| index),
+            totals: trie[at].entry.map(|(_, totals)| totals).unwrap_or_default(),
+        });
+        for (name, child) in &trie[at].children {
+            let child = self.emit(trie, *child, path.join(name), Some(id));
+            let totals = self.nodes[child].totals;
+            let node = &mut self.nodes[id];
+            node.children.push(child);
+            node.totals.add(&totals);
+        }
+        id
+    }
+
+    /// The nodes to draw, depth first, skipping the children of collapsed nodes
+    pub fn visible(&self, collapsed: &HashSet<PathBuf>) -> Vec<usize> {
+        let mut visible = Vec::with_capacity(self.nodes.len());
+        let mut stack: Vec<usize> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            visible.push(id);
+            let node = &self.nodes[id];
+            if !collapsed.contains(&node.path) {
+                stack.extend(node.children.iter().rev());
+            }
+        }
+        visible
+    }
+
+    /// Indexes into `App::entries` of every node_modules below `id`
+    pub fn entries_below(&self, id: usize) -> Vec<usize> {
+        let mut entries = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            entries.extend(node.entry);
+            stack.extend(&node.children);
+        }
+        entries
+    }
+
+    /// Total size of the roots, what the top level size bars are relative to
+    pub fn total_size(&self) -> u64 {
+        self.roots
+            .iter()
+            .map(|root| self.nodes[*root].totals.size)
+            .sum()
+    }
+}
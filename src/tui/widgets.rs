@@ -1,123 +1,379 @@
-use chrono::Utc;
 use ratatui::{
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Wrap},
 };
 use std::borrow::Cow;
-use std::path::PathBuf;
+
+use crate::tui::app::{ListRow, Selection, Usage, View};
+use node_cleaner::config::config::Tier;
+use node_cleaner::file_utils::fs_utils::WalkSnapshot;
+use node_cleaner::report::TierBytes;
+use node_cleaner::utils::read_size::bytes_to_mb;
 
 pub struct HeaderWidget {
-    username: String,
+    roots: String,
+    walk: WalkSnapshot,
 }
 
-pub struct SidebarWidget {
-    content: String,
+pub struct ContentWidget {
+    lines: Vec<String>,
 }
 
-pub struct ContentWidget {
-    content: String,
+pub struct PathTreeWidget<'a> {
+    rows: &'a [ListRow],
+    view: View,
+}
+
+pub struct UsageChartWidget<'a> {
+    usage: &'a Usage,
+    // Inner width of the pane, the bars are scaled to it
+    width: usize,
+}
+
+pub struct StatusWidget {
+    text: String,
+}
+
+pub struct ConfirmPopup {
+    lines: Vec<String>,
 }
 
-pub struct PathTreeWidget {
-    paths: Vec<PathBuf>,
+pub struct ProgressPopup {
+    done: usize,
+    total: usize,
+    freed: u64,
+    failed: usize,
 }
 
 impl HeaderWidget {
-    pub fn new(username: String) -> Self {
-        Self { username }
+    pub fn new(roots: String, walk: WalkSnapshot) -> Self {
+        Self { roots, walk }
     }
 
     pub fn widget(&self) -> Paragraph<'static> {
-        let current_time = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let walk = &self.walk;
+        let value = Style::default().fg(Color::Yellow);
 
-        Paragraph::new(vec![Line::from(vec![
-            Span::raw(Cow::from(
-                "Total time ran: (current_time temp for debugging) ",
+        let mut spans = vec![
+            Span::raw(Cow::from("Elapsed: ")),
+            Span::styled(format!("{:.1?}", walk.elapsed), value),
+            Span::raw(Cow::from(" | Dirs: ")),
+            Span::styled(walk.dirs.to_string(), value),
+            Span::raw(Cow::from(" | Files: ")),
+            Span::styled(walk.files.to_string(), value),
+            Span::raw(Cow::from(" | node_modules: ")),
+            Span::styled(walk.node_modules.to_string(), value),
+            Span::raw(Cow::from(" | ")),
+            Span::styled(format!("{:.0}", walk.entries_per_sec()), value),
+            Span::raw(Cow::from(" entries/s | Ignored: ")),
+            Span::styled(walk.ignored.to_string(), value),
+        ];
+        if walk.errors > 0 {
+            spans.push(Span::raw(Cow::from(" | Unreadable: ")));
+            spans.push(Span::styled(
+                walk.errors.to_string(),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        match (&walk.current, walk.done) {
+            (_, true) => spans.push(Span::styled(
+                Cow::from(" | Walk finished"),
+                Style::default().fg(Color::Green),
             )),
-            Span::styled(Cow::from(current_time), Style::default().fg(Color::Yellow)),
-            //Span::raw(Cow::from(" | User: ")),
-            //Span::styled(
-            //    Cow::from(self.username.clone()),
-            //    Style::default().fg(Color::Yellow),
-            //),
-        ])])
-        .block(
+            (Some(current), false) => {
+                spans.push(Span::raw(Cow::from(" | ")));
+                spans.push(Span::styled(
+                    current.to_string_lossy().into_owned(),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            (None, false) => {}
+        }
+
+        Paragraph::new(vec![Line::from(spans)]).block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Yellow))
-                .title(Cow::from("Node cleaner")),
+                .title(Cow::from(format!("Node cleaner: {}", self.roots))),
         )
     }
 }
 
-impl SidebarWidget {
-    pub fn new(content: String) -> Self {
-        Self { content }
+impl ContentWidget {
+    pub fn new(lines: Vec<String>) -> Self {
+        Self { lines }
     }
 
     pub fn widget(&self) -> Paragraph<'static> {
-        Paragraph::new(vec![Line::from(vec![Span::styled(
-            Cow::from(self.content.clone()),
-            Style::default().fg(Color::LightRed),
-        )])])
-        .block(
+        let lines: Vec<Line<'static>> = self
+            .lines
+            .iter()
+            .map(|line| {
+                Line::from(vec![Span::styled(
+                    line.clone(),
+                    Style::default().fg(Color::Magenta),
+                )])
+            })
+            .collect();
+
+        Paragraph::new(lines).block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::LightRed))
-                .title(Cow::from("Sidebar")),
+                .border_style(Style::default().fg(Color::Magenta))
+                .title(Cow::from("Content")),
         )
     }
 }
 
-impl ContentWidget {
-    pub fn new(content: String) -> Self {
-        Self { content }
+// Width of the size bars in the project tree
+const BAR_WIDTH: usize = 10;
+
+// `share` of BAR_WIDTH cells, in eighths of a cell
+fn size_bar(share: f64) -> String {
+    const PARTS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+    let eighths = (share.clamp(0.0, 1.0) * (BAR_WIDTH * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    let part = PARTS[eighths % 8];
+    if part != ' ' {
+        bar.push(part);
+    }
+    format!("{:<width$}", bar, width = BAR_WIDTH)
+}
+
+impl<'a> PathTreeWidget<'a> {
+    pub fn new(rows: &'a [ListRow], view: View) -> Self {
+        Self { rows, view }
+    }
+
+    pub fn widget(&self) -> List<'static> {
+        // One line per row, the size shows up once the sizer got to it.
+        // Only the rows on screen are styled by the List, so thousands are fine.
+        let items: Vec<ListItem<'static>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mark = match row.selection {
+                    Selection::None => "[ ] ",
+                    Selection::Partial => "[-] ",
+                    Selection::All => "[x] ",
+                };
+                let size = match (row.size, row.pending) {
+                    (_, true) if self.view == View::Projects => format!("{:>12} ", "sizing..."),
+                    (Some(size), _) => format!("{:>9.2} MB ", bytes_to_mb(size)),
+                    (None, _) => format!("{:>9} files ", row.matches),
+                };
+                let folder = match row.folder {
+                    Some(true) => "▾ ",
+                    Some(false) => "▸ ",
+                    None => "  ",
+                };
+                let mut spans = vec![
+                    Span::styled(mark, Style::default().fg(Color::Cyan)),
+                    Span::styled(size, Style::default().fg(Color::Yellow)),
+                    Span::styled(
+                        format!("{:>9.2} MB ", bytes_to_mb(row.reclaimable)),
+                        Style::default().fg(Color::Green),
+                    ),
+                ];
+                if self.view == View::Projects {
+                    spans.push(Span::styled(
+                        format!("{} ", size_bar(row.share)),
+                        Style::default().fg(Color::Blue),
+                    ));
+                }
+                spans.push(Span::raw(format!("{}{}", "  ".repeat(row.depth), folder)));
+                spans.push(Span::styled(
+                    row.label.clone(),
+                    Style::default().fg(Color::LightRed),
+                ));
+                Line::from(spans).into()
+            })
+            .collect();
+
+        let title = match self.view {
+            View::Projects => format!("node_modules found ({})", self.rows.len()),
+            View::Packages => format!("Packages with matches ({})", self.rows.len()),
+        };
+        List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::LightRed))
+                    .title(Cow::from(title)),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    }
+}
+
+pub fn tier_color(tier: Tier) -> Color {
+    match tier {
+        Tier::Safe => Color::Green,
+        Tier::Caution => Color::Yellow,
+        Tier::Danger => Color::Red,
+    }
+}
+
+// Width of the labels in the usage chart, longer ones are cut
+const LABEL_WIDTH: usize = 28;
+
+fn fit_label(label: &str) -> String {
+    if label.chars().count() <= LABEL_WIDTH {
+        return format!("{:<width$}", label, width = LABEL_WIDTH);
+    }
+    let cut: String = label.chars().take(LABEL_WIDTH - 1).collect();
+    format!("{}…", cut)
+}
+
+impl<'a> UsageChartWidget<'a> {
+    pub fn new(usage: &'a Usage, width: usize) -> Self {
+        Self { usage, width }
+    }
+
+    // One run of cells per tier, so a bar shows how its bytes split over
+    // the tiers. Runs end at the rounded cumulative size, so they add up.
+    fn bar(&self, bytes: &TierBytes, largest: u64, width: usize) -> Vec<Span<'static>> {
+        let cells =
+            |bytes: u64| (bytes as f64 / largest.max(1) as f64 * width as f64).round() as usize;
+
+        let mut spans = Vec::new();
+        let (mut cumulative, mut drawn) = (0, 0);
+        for tier in Tier::ALL {
+            cumulative += bytes.get(tier);
+            let end = cells(cumulative);
+            if end > drawn {
+                spans.push(Span::styled(
+                    "█".repeat(end - drawn),
+                    Style::default().fg(tier_color(tier)),
+                ));
+                drawn = end;
+            }
+        }
+        // Too small for a full cell, still show that there is something
+        if drawn == 0
+            && let Some(tier) = Tier::ALL.into_iter().find(|tier| bytes.get(*tier) > 0)
+        {
+            spans.push(Span::styled("▏", Style::default().fg(tier_color(tier))));
+            drawn = 1;
+        }
+        spans.push(Span::raw(" ".repeat(width.saturating_sub(drawn))));
+        spans
     }
 
     pub fn widget(&self) -> Paragraph<'static> {
-        Paragraph::new(vec![Line::from(vec![Span::styled(
-            Cow::from(self.content.clone()),
-            Style::default().fg(Color::Magenta),
-        )])])
-        .block(
+        let usage = self.usage;
+        let mut lines = Vec::new();
+
+        let mut totals = Vec::new();
+        if let Some(size) = usage.size {
+            totals.push(Span::raw(format!(
+                "node_modules {:.2} MB, ",
+                bytes_to_mb(size)
+            )));
+        }
+        totals.push(Span::raw(format!(
+            "reclaimable {:.2} MB:",
+            bytes_to_mb(usage.total.total())
+        )));
+        for tier in Tier::ALL {
+            totals.push(Span::styled(
+                format!(" ■ {} {:.2} MB", tier, bytes_to_mb(usage.total.get(tier))),
+                Style::default().fg(tier_color(tier)),
+            ));
+        }
+        lines.push(Line::from(Span::styled(
+            usage.title.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        lines.push(Line::from(totals));
+        lines.push(Line::default());
+
+        if usage.bars.is_empty() {
+            lines.push(Line::from("Nothing to reclaim here (yet)"));
+        }
+        // Label, a space, the bar, then ` 12345.67 MB`
+        let bar_width = self.width.saturating_sub(LABEL_WIDTH + 13).max(1);
+        let largest = usage.bars.first().map_or(0, |(_, bytes)| bytes.total());
+        for (label, bytes) in &usage.bars {
+            let mut spans = vec![Span::raw(fit_label(label)), Span::raw(" ")];
+            spans.extend(self.bar(bytes, largest, bar_width));
+            spans.push(Span::raw(format!(
+                " {:>8.2} MB",
+                bytes_to_mb(bytes.total())
+            )));
+            lines.push(Line::from(spans));
+        }
+
+        Paragraph::new(lines).block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Magenta))
-                .title(Cow::from("Content")),
+                .title(Cow::from("Space usage")),
         )
     }
 }
 
-impl PathTreeWidget {
-    pub fn new(paths: Vec<PathBuf>) -> Self {
-        Self { paths }
+impl StatusWidget {
+    pub fn new(text: String) -> Self {
+        Self { text }
     }
 
     pub fn widget(&self) -> Paragraph<'static> {
-        // Convert each path to a String (owned, not a Cow)
-        let path_strings: Vec<String> = self
-            .paths
-            .iter()
-            .map(|p| p.to_string_lossy().into_owned())
-            .collect();
+        Paragraph::new(self.text.clone()).style(Style::default().fg(Color::Black).bg(Color::Cyan))
+    }
+}
 
-        // Create a Vec<Line> with 'static spans
-        let lines: Vec<Line<'static>> = path_strings
-            .into_iter()
-            .map(|s| {
-                Line::from(vec![Span::styled(
-                    s, // String is now owned and becomes 'static
-                    Style::default().fg(Color::LightRed),
-                )])
-            })
-            .collect();
+impl ConfirmPopup {
+    pub fn new(lines: Vec<String>) -> Self {
+        Self { lines }
+    }
 
-        Paragraph::new(lines).block(
+    pub fn widget(&self) -> Paragraph<'static> {
+        let lines: Vec<Line<'static>> = self.lines.iter().cloned().map(Line::from).collect();
+        Paragraph::new(lines).wrap(Wrap { trim: true }).block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::LightRed))
-                .title(Cow::from("Paths found")),
+                .border_style(Style::default().fg(Color::Red))
+                .title(Cow::from("Confirm removal [y/n]")),
         )
     }
 }
+
+impl ProgressPopup {
+    pub fn new(done: usize, total: usize, freed: u64, failed: usize) -> Self {
+        Self {
+            done,
+            total,
+            freed,
+            failed,
+        }
+    }
+
+    pub fn widget(&self) -> Gauge<'static> {
+        let ratio = if self.total == 0 {
+            1.0
+        } else {
+            self.done as f64 / self.total as f64
+        };
+        let mut label = format!(
+            "{}/{} files, {:.2} MB freed",
+            self.done,
+            self.total,
+            bytes_to_mb(self.freed)
+        );
+        if self.failed > 0 {
+            label.push_str(&format!(", {} failed", self.failed));
+        }
+
+        Gauge::default()
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Red))
+                    .title(Cow::from("Removing")),
+            )
+            .gauge_style(Style::default().fg(Color::Red))
+            .ratio(ratio.min(1.0))
+            .label(label)
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+/// Parses an age such as `30m`, `12h`, `7d` or `2w`, used for
+/// `--older-than`-style arguments
+pub fn parse_age(raw: &str) -> Result<Duration, String> {
+    let raw = raw.trim();
+    let split = raw
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in '{}', use s, m, h, d or w", raw))?;
+    let (number, unit) = raw.split_at(split);
+
+    let amount: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid age '{}', expected e.g. 7d", raw))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "unknown unit '{}' in '{}', use s, m, h, d or w",
+                unit, raw
+            ));
+        }
+    };
+
+    amount
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("age '{}' is too large", raw))
+}
+
+/// Formats an age with its largest whole unit, e.g. `3d` or `5h`
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        s if s >= 24 * 60 * 60 => format!("{}d", s / (24 * 60 * 60)),
+        s if s >= 60 * 60 => format!("{}h", s / (60 * 60)),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_unit() {
+        assert_eq!(parse_age("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_age("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_age(" 12h "), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse_age("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_age("2w"), Ok(Duration::from_secs(14 * 24 * 60 * 60)));
+    }
+
+    #[test]
+    fn rejects_malformed_ages() {
+        assert!(parse_age("7").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("7y").is_err());
+        assert!(parse_age("-7d").is_err());
+    }
+
+    #[test]
+    fn rejects_ages_that_overflow() {
+        assert!(parse_age("99999999999999999w").is_err());
+        assert!(parse_age("99999999999999999999s").is_err());
+        assert_eq!(
+            parse_age(&format!("{}s", u64::MAX)),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+    }
+}
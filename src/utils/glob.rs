@@ -0,0 +1,366 @@
+use std::fmt;
+
+// A single token inside one path segment of a glob
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    // `*` - any run of characters inside a segment
+    AnyChars,
+    // `?` - exactly one character
+    AnyChar,
+    // `[abc]`, `[a-z]`, `[!abc]`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+// One `/`-separated segment of a glob
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    // `**` - zero or more whole path segments
+    AnyDepth,
+    Tokens(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobError {
+    pub pattern: String,
+    pub message: String,
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid glob '{}': {}", self.pattern, self.message)
+    }
+}
+
+impl std::error::Error for GlobError {}
+
+/// A glob compiled once into per-segment tokens.
+///
+/// Supports `*`, `?`, `[...]` character classes (with `!`/`^` negation and
+/// ranges) inside a segment and `**` as a whole segment matching any depth.
+/// Leading and trailing `/` are ignored here, callers decide what anchoring
+/// and directory-only mean for their use case.
+///
+/// A case-insensitive glob is lowercased at compile time, so the input must
+/// be lowercased by the caller (once per path, not once per pattern).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    pattern: String,
+    segments: Vec<Segment>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        Self::compile(pattern, false)
+    }
+
+    pub fn new_case_insensitive(pattern: &str) -> Result<Self, GlobError> {
+        Self::compile(pattern, true)
+    }
+
+    fn compile(pattern: &str, case_insensitive: bool) -> Result<Self, GlobError> {
+        let source = if case_insensitive {
+            pattern.to_lowercase()
+        } else {
+            pattern.to_string()
+        };
+
+        let mut segments = Vec::new();
+        for raw in source.split('/').filter(|s| !s.is_empty()) {
+            if raw == "**" {
+                // Consecutive `**` segments are equivalent to one
+                if segments.last() != Some(&Segment::AnyDepth) {
+                    segments.push(Segment::AnyDepth);
+                }
+            } else {
+                segments.push(Segment::Tokens(parse_segment(raw, pattern)?));
+            }
+        }
+
+        if segments.is_empty() {
+            return Err(GlobError {
+                pattern: pattern.to_string(),
+                message: String::from("pattern is empty"),
+            });
+        }
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            segments,
+        })
+    }
+
+    /// The pattern as it was written
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// True if the glob is a single segment without `**`
+    pub fn is_single_segment(&self) -> bool {
+        matches!(self.segments.as_slice(), [Segment::Tokens(_)])
+    }
+
+    /// Matches a single name (no separators) against a single-segment glob
+    pub fn matches_name(&self, name: &str) -> bool {
+        match self.segments.as_slice() {
+            [Segment::Tokens(tokens)] => match_tokens(tokens, &name.chars().collect::<Vec<_>>()),
+            _ => false,
+        }
+    }
+
+    /// Returns the length of the shortest leading slice of `segments` that the
+    /// glob matches, e.g. `**/.cache` matches `home/u/.cache/x` at length 3
+    pub fn match_prefix_len(&self, segments: &[&str]) -> Option<usize> {
+        (1..=segments.len()).find(|&len| match_segments(&self.segments, &segments[..len]))
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+// True if the string contains any glob meta characters
+pub fn has_glob_meta(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn parse_segment(raw: &str, pattern: &str) -> Result<Vec<Token>, GlobError> {
+    let chars: Vec<char> = raw.chars().collect();
+    let mut tokens = Vec::with_capacity(chars.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                // `a**b` inside a segment behaves like `a*b`
+                if tokens.last() != Some(&Token::AnyChars) {
+                    tokens.push(Token::AnyChars);
+                }
+                i += 1;
+            }
+            '?' => {
+                tokens.push(Token::AnyChar);
+                i += 1;
+            }
+            '[' => {
+                let (token, next) = parse_class(&chars, i, pattern)?;
+                tokens.push(token);
+                i = next;
+            }
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Literal(chars[i + 1]));
+                i += 2;
+            }
+            c => {
+                tokens.push(Token::Literal(c));
+                i += 1;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Parses a `[...]` class starting at `start`, returns the token and the index after `]`
+fn parse_class(chars: &[char], start: usize, pattern: &str) -> Result<(Token, usize), GlobError> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        // A `]` directly after `[` or `[!` is a literal
+        if c == ']' && !first {
+            return Ok((Token::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+
+        if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
+            let (lo, hi) = (c, chars[i + 2]);
+            if lo > hi {
+                return Err(GlobError {
+                    pattern: pattern.to_string(),
+                    message: format!("invalid range '{}-{}'", lo, hi),
+                });
+            }
+            ranges.push((lo, hi));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+
+    Err(GlobError {
+        pattern: pattern.to_string(),
+        message: String::from("unclosed character class"),
+    })
+}
+
+fn match_segments(pattern: &[Segment], segments: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((Segment::AnyDepth, rest)) => {
+            (0..=segments.len()).any(|skip| match_segments(rest, &segments[skip..]))
+        }
+        Some((Segment::Tokens(tokens), rest)) => match segments.split_first() {
+            Some((first, remaining)) => {
+                let chars: Vec<char> = first.chars().collect();
+                match_tokens(tokens, &chars) && match_segments(rest, remaining)
+            }
+            None => false,
+        },
+    }
+}
+
+// Wildcard matching of one segment with single-star backtracking
+fn match_tokens(tokens: &[Token], input: &[char]) -> bool {
+    let (mut t, mut i) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while i < input.len() {
+        match tokens.get(t) {
+            Some(Token::AnyChars) => {
+                star = Some((t, i));
+                t += 1;
+                continue;
+            }
+            Some(token) if token_matches(token, input[i]) => {
+                t += 1;
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        // Mismatch: let the last `*` swallow one more character
+        match star {
+            Some((star_t, star_i)) => {
+                t = star_t + 1;
+                i = star_i + 1;
+                star = Some((star_t, star_i + 1));
+            }
+            None => return false,
+        }
+    }
+
+    tokens[t..].iter().all(|token| *token == Token::AnyChars)
+}
+
+fn token_matches(token: &Token, c: char) -> bool {
+    match token {
+        Token::Literal(l) => *l == c,
+        Token::AnyChar => true,
+        Token::AnyChars => true,
+        Token::Class { negated, ranges } => {
+            let inside = ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+            inside != *negated
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        Glob::new(pattern).unwrap()
+    }
+
+    fn error(pattern: &str) -> String {
+        Glob::new(pattern).unwrap_err().message
+    }
+
+    fn prefix_len(pattern: &str, path: &str) -> Option<usize> {
+        let segments: Vec<&str> = path.split('/').collect();
+        glob(pattern).match_prefix_len(&segments)
+    }
+
+    #[test]
+    fn escapes_match_literally() {
+        assert!(glob(r"a\*b").matches_name("a*b"));
+        assert!(!glob(r"a\*b").matches_name("axb"));
+        assert!(glob(r"\[x]").matches_name("[x]"));
+        assert!(!glob(r"\[x]").matches_name("x"));
+        assert!(glob(r"\?").matches_name("?"));
+        assert!(!glob(r"\?").matches_name("a"));
+        // A trailing backslash has nothing to escape and stays itself
+        assert!(glob(r"a\").matches_name(r"a\"));
+    }
+
+    #[test]
+    fn wildcards_and_classes() {
+        assert!(glob("*.md").matches_name("README.md"));
+        assert!(!glob("*.md").matches_name("README.mdx"));
+        assert!(glob("a**b").matches_name("axxb"));
+        assert!(glob("?.js").matches_name("a.js"));
+        assert!(!glob("?.js").matches_name("ab.js"));
+        assert!(glob("[a-c]x").matches_name("bx"));
+        assert!(!glob("[a-c]x").matches_name("dx"));
+        assert!(glob("[!a-c]x").matches_name("dx"));
+        assert!(!glob("[^a-c]x").matches_name("ax"));
+        // `]` right after `[` is part of the class, `-` before `]` is literal
+        assert!(glob("[]]").matches_name("]"));
+        assert!(glob("[a-]").matches_name("-"));
+    }
+
+    #[test]
+    fn case_insensitive_globs_are_lowercased() {
+        let glob = Glob::new_case_insensitive("README*").unwrap();
+        assert!(glob.matches_name("readme.md"));
+        assert!(!glob.matches_name("README.md"));
+        assert_eq!(glob.as_str(), "README*");
+    }
+
+    #[test]
+    fn unclosed_classes_are_rejected() {
+        assert_eq!(error("[abc"), "unclosed character class");
+        assert_eq!(error("src/[!a"), "unclosed character class");
+        assert_eq!(error("[]"), "unclosed character class");
+    }
+
+    #[test]
+    fn reversed_ranges_are_rejected() {
+        assert_eq!(error("[z-a]"), "invalid range 'z-a'");
+        assert!(Glob::new("[a-a]").is_ok());
+    }
+
+    #[test]
+    fn empty_patterns_are_rejected() {
+        assert_eq!(error(""), "pattern is empty");
+        assert_eq!(error("//"), "pattern is empty");
+    }
+
+    #[test]
+    fn any_depth_segments_collapse() {
+        assert_eq!(glob("a/**/**/b").segments, glob("a/**/b").segments);
+        assert_eq!(glob("**/**").segments, glob("**").segments);
+        assert!(!glob("**").is_single_segment());
+        assert!(glob("*.md").is_single_segment());
+
+        assert_eq!(prefix_len("a/**/**/b", "a/b"), Some(2));
+        assert_eq!(prefix_len("a/**/**/b", "a/x/y/b"), Some(4));
+        assert_eq!(prefix_len("a/**/**/b", "a/x/y"), None);
+    }
+
+    #[test]
+    fn match_prefix_len_finds_the_shortest_match() {
+        assert_eq!(prefix_len("**/.cache", "home/u/.cache/x"), Some(3));
+        assert_eq!(prefix_len("**/.cache", "home/u/cache/x"), None);
+        assert_eq!(prefix_len("home", "home/u/x"), Some(1));
+        assert_eq!(prefix_len("home/*", "home/u/x"), Some(2));
+        assert_eq!(prefix_len("u", "home/u/x"), None);
+        // `**` also matches nothing, but a prefix is never empty
+        assert_eq!(prefix_len("**", "home/u"), Some(1));
+        assert_eq!(glob("**").match_prefix_len(&[]), None);
+    }
+}
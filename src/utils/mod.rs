@@ -1,2 +1,4 @@
 pub mod read_size;
 pub mod g_utils;
+pub mod glob;
+pub mod age;
@@ -1,6 +1,10 @@
 use std::fs;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::Path;
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+use crate::error::ScanError;
 
 /// Gets the size of a file in bytes
 ///
@@ -75,50 +79,46 @@ pub fn bytes_to_mb(bytes: u64) -> f64 {
     bytes as f64 / 1_048_576.0
 }
 
-/// Analyzes a path and returns its size information
-///
-/// # Arguments
-/// * `path` - Path to analyze (file or directory)
-///
-/// # Returns
-/// * `Ok((u64, f64))` - Tuple containing size in bytes and in megabytes
-/// * `Err` - If the path doesn't exist, isn't accessible, or has other issues
-/// *  Gets the size of a single path (file or directory)
-pub fn get_path_size<P: AsRef<Path>>(path: P) -> io::Result<(u64, f64)> {
-    let path_ref = path.as_ref();
-
-    let size_bytes = if path_ref.is_file() {
-        get_file_size_bytes(path_ref)?
-    } else if path_ref.is_dir() {
-        get_directory_size_bytes(path_ref)?
-    } else {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Path does not exist or is not a file or directory",
-        ));
-    };
-
-    let size_mb = bytes_to_mb(size_bytes);
-    Ok((size_bytes, size_mb))
+/// Totals of a directory tree, see `get_tree_size`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TreeStats {
+    pub bytes: u64,
+    pub files: u64,
+    // Newest modification time of any entry, the root directory included
+    pub modified: Option<SystemTime>,
 }
 
-/// Gets the total size of multiple paths (files and/or directories)
+/// Sums the size and count of every file below a directory without following symlinks
 ///
 /// # Arguments
-/// * `paths` - A vector of paths to analyze
+/// * `path` - Path to the directory
 ///
 /// # Returns
-/// * `Ok((u64, f64))` - Tuple containing total size in bytes and in megabytes
-/// * `Err` - If any path is invalid or inaccessible
-pub fn get_paths_size(paths: &[PathBuf]) -> io::Result<(u64, f64)> {
-    let mut total_bytes = 0;
-
-    for path in paths {
-        let (bytes, _) = get_path_size(path)?;
-        total_bytes += bytes;
+/// * `(TreeStats, Vec<ScanError>)` - Total size in bytes, number of files and
+///   newest mtime of every readable entry, and why the others were left out
+///
+/// # Notes
+/// Unlike `get_directory_size_bytes` this never fails as a whole: unreadable
+/// entries are skipped, and symlinks (such as `node_modules/.bin`) count as their
+/// own size instead of their target's, so linked trees are neither double counted nor cycled.
+pub fn get_tree_size<P: AsRef<Path>>(path: P) -> (TreeStats, Vec<ScanError>) {
+    let mut stats = TreeStats::default();
+    let mut errors = Vec::new();
+    for entry in WalkDir::new(path) {
+        let (entry, metadata) = match entry.and_then(|entry| Ok((entry.metadata()?, entry))) {
+            Ok((metadata, entry)) => (entry, metadata),
+            Err(e) => {
+                errors.push(ScanError::from_walkdir(&e));
+                continue;
+            }
+        };
+        if let Ok(modified) = metadata.modified() {
+            stats.modified = stats.modified.max(Some(modified));
+        }
+        if !entry.file_type().is_dir() {
+            stats.bytes += metadata.len();
+            stats.files += 1;
+        }
     }
-
-    let total_mb = bytes_to_mb(total_bytes);
-    Ok((total_bytes, total_mb))
+    (stats, errors)
 }
-fn main() {}